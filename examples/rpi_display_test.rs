@@ -23,7 +23,7 @@ fn main() {
     let raw_image = ImageRaw::<BinaryColor>::new(RUSTLOGO, 64);
     let mut inky = Inky1608::new(Some((250, 122)), None, 0, 22, 27, 17, false, false, None, None).expect("inky");
     let inky_info = format!("{}", inky);
-    inky.set_border(Colour::Black);
+    inky.set_border(Colour::Black).expect("border");
     let mut style = MonoTextStyle::new(&PROFONT_10_POINT, BinaryColor::On);
     Text::new(&inky_info, Point::new(10, 20), style).draw(&mut inky).expect("text");
     style = MonoTextStyle::new(&PROFONT_14_POINT, BinaryColor::On);
@@ -0,0 +1,85 @@
+// Async (tokio) versions of the calls that block while the panel is busy.
+//
+// SPI transfers and the reset sequence run on tokio's blocking pool, and BUSY is
+// awaited through a sysfs GPIO edge stream rather than polled, so a refresh never
+// ties up a runtime thread.
+use super::*;
+use futures::StreamExt;
+use linux_embedded_hal::sysfs_gpio::{Edge, Error as GpioError};
+use tokio::task::spawn_blocking;
+
+impl Inky1608 {
+    /// Async equivalent of `flush()`. Unlike `flush()` this also waits for the
+    /// panel to finish refreshing before returning.
+    #[allow(dead_code)]
+    pub async fn flush_async(&mut self) -> Result<(), GpioError> {
+        let (buf_a, buf_b) = self.pack_planes(&self.framebuffer, &self.accent);
+        let lut = self.lut;
+        let commands = self.frame_commands(buf_a, buf_b, &lut);
+        let (dc, reset, busy) = (self.dc_pin.get_pin_num(), self.reset_pin.get_pin_num(), self.busy_pin.get_pin_num());
+        let chunk_size = self.spi_chunk_size;
+
+        if let Some(started) = self.refreshing {
+            tokio::time::sleep(BUSY_SETTLE.saturating_sub(started.elapsed())).await;
+            self.busy_wait_async().await?;
+            self.refreshing = None;
+        }
+
+        if !self.initialised {
+            if !self.exported {
+                blocking(move || export_pins(&Pin::new(dc), &Pin::new(reset), &Pin::new(busy))).await?;
+                self.exported = true;
+            }
+            let spidev = Arc::clone(&self.spidev);
+            blocking(move || hardware_reset(&spidev, &Pin::new(dc), &Pin::new(reset), chunk_size)).await?;
+            self.busy_wait_async().await?;
+            self.send_commands_async(self.init_commands()).await?;
+            self.initialised = true;
+        }
+
+        self.send_commands_async(commands).await?;
+        self.busy_wait_async().await?;
+
+        let spidev = Arc::clone(&self.spidev);
+        blocking(move || send_spi_command(&spidev, &Pin::new(dc), chunk_size, MASTER_ACTIVATE, None)).await?;
+        tokio::time::sleep(BUSY_SETTLE).await;
+        self.busy_wait_async().await?;
+
+        self.sent();
+        Ok(())
+    }
+
+    async fn send_commands_async(&self, commands: Vec<(u8, Vec<u8>)>) -> Result<(), GpioError> {
+        let spidev = Arc::clone(&self.spidev);
+        let dc = self.dc_pin.get_pin_num();
+        let chunk_size = self.spi_chunk_size;
+        blocking(move || {
+            let dc_pin = Pin::new(dc);
+            for (command, data) in commands {
+                send_spi_command(&spidev, &dc_pin, chunk_size, command, Some(&data))?;
+            }
+            Ok(())
+        }).await
+    }
+
+    async fn busy_wait_async(&self) -> Result<(), GpioError> {
+        self.busy_pin.set_edge(Edge::FallingEdge)?;
+        // Open the stream before checking the pin so a falling edge in between
+        // isn't missed.
+        let mut falling = self.busy_pin.get_value_stream()?;
+        while self.busy_pin.get_value()? != 0 {
+            match falling.next().await {
+                Some(value) => { value?; },
+                None => break
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn blocking<F>(f: F) -> Result<(), GpioError>
+where
+    F: FnOnce() -> Result<(), GpioError> + Send + 'static,
+{
+    spawn_blocking(f).await.map_err(|e| GpioError::Unexpected(format!("blocking task failed: {}", e)))?
+}
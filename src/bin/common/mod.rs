@@ -0,0 +1,51 @@
+// Display options shared by the command line tools.
+use clap::Args;
+use inky_ssd1608::{Colour, Inky1608, SpiConfig};
+use std::error::Error;
+
+#[derive(Args)]
+pub struct DisplayArgs {
+    /// Rotation in degrees (0, 90, 180, 270 or -90). Defaults to landscape.
+    #[arg(long, global = true, allow_hyphen_values = true)]
+    rotation: Option<i16>,
+    /// Mirror the image horizontally.
+    #[arg(long, global = true)]
+    h_flip: bool,
+    /// Mirror the image vertically.
+    #[arg(long, global = true)]
+    v_flip: bool,
+    /// Panel colour (black, red, yellow, red-ht or gray2). Read from the EEPROM if not given.
+    #[arg(long, global = true)]
+    colour: Option<Colour>,
+    /// SPI chip select channel.
+    #[arg(long, global = true, default_value_t = 0)]
+    cs: u16,
+    /// SPI bus number.
+    #[arg(long, global = true, default_value_t = 0)]
+    spi_bus: u16,
+    /// SPI clock speed in Hz.
+    #[arg(long, global = true, default_value_t = 488_000)]
+    spi_speed: u32,
+    /// Data/command GPIO.
+    #[arg(long, global = true, default_value_t = 22)]
+    dc_pin: u64,
+    /// Reset GPIO.
+    #[arg(long, global = true, default_value_t = 27)]
+    reset_pin: u64,
+    /// Busy GPIO.
+    #[arg(long, global = true, default_value_t = 17)]
+    busy_pin: u64,
+}
+
+impl DisplayArgs {
+    /// Open and configure the display.
+    pub fn open(&self) -> Result<Inky1608, Box<dyn Error>> {
+        let spi = SpiConfig { bus: self.spi_bus, speed_hz: self.spi_speed }.open(self.cs)?;
+        let mut inky = Inky1608::new(Some((250, 122)), self.colour, self.cs, self.dc_pin, self.reset_pin,
+            self.busy_pin, self.h_flip, self.v_flip, Some(spi), None)?;
+        if let Some(rotation) = self.rotation {
+            inky.set_rotation(rotation)?;
+        }
+        Ok(inky)
+    }
+}
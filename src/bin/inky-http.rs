@@ -0,0 +1,27 @@
+// HTTP server for updating the display with curl. See `inky_ssd1608::http`
+// for the routes.
+//
+// Build with `cargo build --release --features http`.
+use clap::Parser;
+use common::DisplayArgs;
+use inky_ssd1608::http::{Server, DEFAULT_PORT};
+use std::error::Error;
+
+mod common;
+
+#[derive(Parser)]
+#[command(name = "inky-http", version, about = "Serve an Inky pHAT (SSD1608) e-paper display over HTTP on 127.0.0.1")]
+struct Cli {
+    #[command(flatten)]
+    display: DisplayArgs,
+    /// Port to listen on.
+    #[arg(long, default_value_t = DEFAULT_PORT)]
+    port: u16,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let server = Server::bind(cli.port, cli.display.open()?)?;
+    server.run()?;
+    Ok(())
+}
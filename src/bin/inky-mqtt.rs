@@ -0,0 +1,49 @@
+// Shows images, text and commands published to an MQTT broker. See
+// `inky_ssd1608::mqtt` for the topics and payloads.
+//
+// Build with `cargo build --release --features mqtt`.
+use clap::Parser;
+use common::DisplayArgs;
+use inky_ssd1608::mqtt::{Subscriber, Topics, DEFAULT_PORT};
+use std::error::Error;
+
+mod common;
+
+#[derive(Parser)]
+#[command(name = "inky-mqtt", version, about = "Show MQTT messages on an Inky pHAT (SSD1608) e-paper display")]
+struct Cli {
+    #[command(flatten)]
+    display: DisplayArgs,
+    /// Broker host name or address.
+    #[arg(long, default_value = "localhost")]
+    host: String,
+    /// Broker port.
+    #[arg(long, default_value_t = DEFAULT_PORT)]
+    port: u16,
+    /// MQTT client id, which must be unique on the broker.
+    #[arg(long, default_value = "inky-ssd1608")]
+    client_id: String,
+    /// Prefix of the image, text and command topics.
+    #[arg(long, default_value = "inky")]
+    prefix: String,
+    /// Topic for images, instead of <prefix>/image.
+    #[arg(long)]
+    image_topic: Option<String>,
+    /// Topic for text, instead of <prefix>/text.
+    #[arg(long)]
+    text_topic: Option<String>,
+    /// Topic for commands, instead of <prefix>/command.
+    #[arg(long)]
+    command_topic: Option<String>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let mut topics = Topics::new(&cli.prefix);
+    topics.image = cli.image_topic.unwrap_or(topics.image);
+    topics.text = cli.text_topic.unwrap_or(topics.text);
+    topics.command = cli.command_topic.unwrap_or(topics.command);
+    let subscriber = Subscriber::new(&cli.host, cli.port, &cli.client_id, topics, cli.display.open()?);
+    subscriber.run()?;
+    Ok(())
+}
@@ -0,0 +1,108 @@
+// Command line tool for driving an Inky pHAT from shell scripts.
+//
+// Build with `cargo build --release --features cli`.
+use clap::{Parser, Subcommand};
+use common::DisplayArgs;
+use embedded_graphics::{
+    mono_font::ascii::{FONT_10X20, FONT_6X10, FONT_9X15},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+};
+use inky_ssd1608::dither::Dither;
+use inky_ssd1608::images::{self, ScaleMode};
+use inky_ssd1608::layout::{HAlign, TextBox, VAlign};
+use inky_ssd1608::scene::Scene;
+use std::error::Error;
+use std::path::PathBuf;
+
+mod common;
+
+#[derive(Parser)]
+#[command(name = "inky", version, about = "Drive an Inky pHAT (SSD1608) e-paper display")]
+struct Cli {
+    #[command(flatten)]
+    display: DisplayArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the display details read from its EEPROM.
+    Info,
+    /// Clear the display to white.
+    Clear,
+    /// Show an image file (PNG, JPEG, BMP or PBM).
+    Show {
+        image: PathBuf,
+        /// How to scale the image: fit, fill or stretch.
+        #[arg(long, default_value = "fit")]
+        scale: ScaleMode,
+        /// Dithering: none, floyd-steinberg, atkinson, bayer4, bayer8 or blue-noise.
+        #[arg(long, default_value = "none")]
+        dither: Dither,
+    },
+    /// Show some text, wrapped and centred in the largest font that fits ("\n" starts a new line).
+    Text { text: String },
+    /// Show a TOML or JSON scene file.
+    Scene { file: PathBuf },
+    /// Set the border colour (black, white, red, yellow or floating).
+    Border { colour: String },
+    /// Cycle the panel between black and white to remove ghosting.
+    Clean {
+        #[arg(long, default_value_t = 3)]
+        cycles: u32,
+    },
+    /// Put the controller into deep sleep.
+    Sleep,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let mut inky = cli.display.open()?;
+
+    match cli.command {
+        Command::Info => println!("{}", inky),
+        Command::Clear => {
+            inky.clear(BinaryColor::Off)?;
+            inky.flush()?;
+        }
+        Command::Show { image, scale, dither } => {
+            inky.draw_image_dithered(&images::open(image)?, scale, dither);
+            inky.flush()?;
+        }
+        Command::Text { text } => {
+            inky.clear(BinaryColor::Off)?;
+            let text = text.replace("\\n", "\n");
+            let bounds = Rectangle::new(Point::zero(), inky.size());
+            TextBox::with_fonts(&text, bounds, &[&FONT_10X20, &FONT_9X15, &FONT_6X10])
+                .align(HAlign::Centre, VAlign::Middle)
+                .draw(&mut inky)?;
+            inky.flush()?;
+        }
+        Command::Scene { file } => Scene::load(file)?.render(&mut inky)?,
+        Command::Border { colour } => {
+            match colour.as_str() {
+                "floating" => inky.set_border_floating(),
+                _ => inky.set_border(colour.parse()?)?,
+            }
+            inky.refresh_border()?;
+        }
+        Command::Clean { cycles } => {
+            for _ in 0..cycles {
+                for colour in [BinaryColor::On, BinaryColor::Off] {
+                    inky.clear(colour)?;
+                    inky.flush()?;
+                    inky.wait_refresh()?;
+                }
+            }
+        }
+        Command::Sleep => {
+            inky.reinit()?;
+            inky.sleep()?;
+        }
+    }
+    // Dropping the display waits for the refresh to finish and releases the GPIOs.
+    Ok(())
+}
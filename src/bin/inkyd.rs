@@ -0,0 +1,39 @@
+// Daemon that owns the display and takes frames and commands from other
+// processes over a Unix socket. See `inky_ssd1608::daemon` for the protocol.
+//
+// Build with `cargo build --release --features daemon`.
+use clap::Parser;
+use common::DisplayArgs;
+use inky_ssd1608::daemon::{Server, DEFAULT_SOCKET};
+use std::error::Error;
+use std::path::PathBuf;
+
+mod common;
+
+#[derive(Parser)]
+#[command(name = "inkyd", version, about = "Share an Inky pHAT (SSD1608) e-paper display between processes")]
+struct Cli {
+    #[command(flatten)]
+    display: DisplayArgs,
+    /// Socket to listen on.
+    #[arg(long, default_value = DEFAULT_SOCKET)]
+    socket: PathBuf,
+    /// Socket permissions in octal, e.g. 660 to only let the daemon's group
+    /// connect. By default any user can.
+    #[arg(long, value_parser = octal)]
+    socket_mode: Option<u32>,
+}
+
+fn octal(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode, 8).map_err(|e| format!("not an octal mode: {}", e))
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let server = Server::bind(&cli.socket, cli.display.open()?)?;
+    if let Some(mode) = cli.socket_mode {
+        server.set_mode(mode)?;
+    }
+    server.run()?;
+    Ok(())
+}
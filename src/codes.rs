@@ -0,0 +1,368 @@
+// QR codes and 1D barcodes, drawn with whole-pixel modules so they scan reliably.
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+};
+use crate::layout::centred;
+use std::io::{Error, ErrorKind};
+
+/// QR error correction level: how much of the code can be damaged or covered
+/// and still be read. Higher levels make the code larger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EccLevel {
+    /// About 7% recoverable.
+    Low,
+    /// About 15% recoverable.
+    #[default]
+    Medium,
+    /// About 25% recoverable.
+    Quartile,
+    /// About 30% recoverable.
+    High,
+}
+
+impl std::str::FromStr for EccLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "l" | "low" => Ok(EccLevel::Low),
+            "m" | "medium" => Ok(EccLevel::Medium),
+            "q" | "quartile" => Ok(EccLevel::Quartile),
+            "h" | "high" => Ok(EccLevel::High),
+            _ => Err(Error::new(ErrorKind::InvalidInput, format!("unknown ECC level '{}'", s)))
+        }
+    }
+}
+
+/// A QR code. Modules are drawn as the largest whole number of pixels that
+/// fits the area (by default the whole display), with the four module quiet
+/// zone scanners need, centred.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Qr {
+    width: u32,
+    modules: Vec<bool>,
+    area: Option<Rectangle>,
+}
+
+impl Qr {
+    /// Encode `data`, failing if it is too long for a QR code at this level.
+    pub fn new(data: &[u8], ecc: EccLevel) -> Result<Qr, Error> {
+        let level = match ecc {
+            EccLevel::Low => qrcode::EcLevel::L,
+            EccLevel::Medium => qrcode::EcLevel::M,
+            EccLevel::Quartile => qrcode::EcLevel::Q,
+            EccLevel::High => qrcode::EcLevel::H,
+        };
+        let code = qrcode::QrCode::with_error_correction_level(data, level)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("can't encode QR code: {}", e)))?;
+        Ok(Qr {
+            width: code.width() as u32,
+            modules: code.to_colors().into_iter().map(|colour| colour == qrcode::Color::Dark).collect(),
+            area: None,
+        })
+    }
+
+    /// Fit the code in `area` rather than the whole display.
+    pub fn fit(mut self, area: Rectangle) -> Qr {
+        self.area = Some(area);
+        self
+    }
+
+    /// Number of modules along each side, not counting the quiet zone.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Pixels per module when drawn in `area`, or None if it won't fit.
+    pub fn module_size(&self, area: Rectangle) -> Option<u32> {
+        let size = area.size.width.min(area.size.height) / (self.width + 2 * QR_QUIET);
+        (size > 0).then_some(size)
+    }
+}
+
+impl Drawable for Qr {
+    type Color = BinaryColor;
+    type Output = ();
+
+    /// Draws nothing if the area is too small for one pixel per module.
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let area = self.area.unwrap_or_else(|| target.bounding_box());
+        let module = match self.module_size(area) {
+            Some(module) => module,
+            None => return Ok(())
+        };
+        let side = (self.width + 2 * QR_QUIET) * module;
+        let origin = centred(area, Size::new(side, side));
+        target.fill_solid(&Rectangle::new(origin, Size::new(side, side)), BinaryColor::Off)?;
+        let quiet = (QR_QUIET * module) as i32;
+        let origin = origin + Point::new(quiet, quiet);
+        for (i, &dark) in self.modules.iter().enumerate() {
+            if dark {
+                let (x, y) = (i as u32 % self.width, i as u32 / self.width);
+                let top_left = origin + Point::new((x * module) as i32, (y * module) as i32);
+                target.fill_solid(&Rectangle::new(top_left, Size::new(module, module)), BinaryColor::On)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A 1D barcode. Bars are drawn as the largest whole number of pixels wide
+/// that fits the area (by default the whole display) with its quiet zones,
+/// centred horizontally and using the full height.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Barcode {
+    // One entry per module, true for a bar.
+    modules: Vec<bool>,
+    // Quiet zone modules either side.
+    quiet: (u32, u32),
+    area: Option<Rectangle>,
+}
+
+impl Barcode {
+    /// Code 128 of printable ASCII text. Runs of digits are packed two to a
+    /// symbol using code set C.
+    pub fn code128(text: &str) -> Result<Barcode, Error> {
+        if text.is_empty() || !text.bytes().all(|b| (32..127).contains(&b)) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Code 128 text must be printable ASCII"));
+        }
+        let mut modules = vec![];
+        for value in code128_values(text.as_bytes()) {
+            for (i, width) in CODE128[value].bytes().enumerate() {
+                modules.extend(std::iter::repeat_n(i % 2 == 0, (width - b'0').into()));
+            }
+        }
+        Ok(Barcode { modules, quiet: (10, 10), area: None })
+    }
+
+    /// EAN-13 from 12 digits, or 13 digits including a correct check digit.
+    pub fn ean13(digits: &str) -> Result<Barcode, Error> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidInput, message.to_string());
+        let values: Vec<u8> = digits.bytes().map(|b| b.wrapping_sub(b'0')).collect();
+        if !(values.len() == 12 || values.len() == 13) || values.iter().any(|&d| d > 9) {
+            return Err(invalid("EAN-13 needs 12 or 13 digits"));
+        }
+        let check = ean13_check(&values[..12]);
+        if values.len() == 13 && values[12] != check {
+            return Err(invalid("EAN-13 check digit is wrong"));
+        }
+        let mut values = values[..12].to_vec();
+        values.push(check);
+
+        let mut pattern = String::from("101");
+        let parity = EAN_PARITY[usize::from(values[0])].as_bytes();
+        for (i, &digit) in values[1..7].iter().enumerate() {
+            let table = if parity[i] == b'L' { &EAN_L } else { &EAN_G };
+            pattern.push_str(table[usize::from(digit)]);
+        }
+        pattern.push_str("01010");
+        for &digit in &values[7..] {
+            // R codes are the complement of L codes.
+            pattern.extend(EAN_L[usize::from(digit)].chars().map(|c| if c == '0' { '1' } else { '0' }));
+        }
+        pattern.push_str("101");
+        Ok(Barcode { modules: pattern.chars().map(|c| c == '1').collect(), quiet: (11, 7), area: None })
+    }
+
+    /// Fit the barcode in `area` rather than the whole display.
+    pub fn fit(mut self, area: Rectangle) -> Barcode {
+        self.area = Some(area);
+        self
+    }
+
+    /// Width in modules, including quiet zones.
+    pub fn width(&self) -> u32 {
+        self.modules.len() as u32 + self.quiet.0 + self.quiet.1
+    }
+
+    /// Pixels per module when drawn in `area`, or None if it won't fit.
+    pub fn module_size(&self, area: Rectangle) -> Option<u32> {
+        let size = area.size.width / self.width();
+        (size > 0 && area.size.height > 0).then_some(size)
+    }
+}
+
+impl Drawable for Barcode {
+    type Color = BinaryColor;
+    type Output = ();
+
+    /// Draws nothing if the area is too narrow for one pixel per module.
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let area = self.area.unwrap_or_else(|| target.bounding_box());
+        let module = match self.module_size(area) {
+            Some(module) => module,
+            None => return Ok(())
+        };
+        let size = Size::new(self.width() * module, area.size.height);
+        let origin = centred(area, size);
+        target.fill_solid(&Rectangle::new(origin, size), BinaryColor::Off)?;
+        let origin = origin + Point::new((self.quiet.0 * module) as i32, 0);
+        for (i, &bar) in self.modules.iter().enumerate() {
+            if bar {
+                let top_left = origin + Point::new((i as u32 * module) as i32, 0);
+                target.fill_solid(&Rectangle::new(top_left, Size::new(module, size.height)), BinaryColor::On)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Width in modules of the light margin required around a QR code.
+const QR_QUIET: u32 = 4;
+
+const CODE128_START_B: usize = 104;
+const CODE128_START_C: usize = 105;
+const CODE128_CODE_B: usize = 100;
+const CODE128_CODE_C: usize = 99;
+const CODE128_STOP: usize = 106;
+
+// Symbol values for text, including start, check and stop symbols. Code set
+// B is used except for runs of at least four digits (at the start or end) or
+// six (in the middle), which switch to code set C.
+fn code128_values(text: &[u8]) -> Vec<usize> {
+    let digits_at = |i: usize| text[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let worth_c = |i: usize| {
+        let run = digits_at(i);
+        run >= 6 || (run >= 4 && (i == 0 || i + run == text.len()))
+    };
+
+    let mut in_c = worth_c(0);
+    let mut values = vec![if in_c { CODE128_START_C } else { CODE128_START_B }];
+    let mut i = 0;
+    while i < text.len() {
+        if in_c {
+            if digits_at(i) >= 2 {
+                values.push(usize::from((text[i] - b'0') * 10 + text[i + 1] - b'0'));
+                i += 2;
+                continue;
+            }
+            values.push(CODE128_CODE_B);
+            in_c = false;
+        } else if worth_c(i) && digits_at(i) % 2 == 0 {
+            values.push(CODE128_CODE_C);
+            in_c = true;
+            continue;
+        }
+        // An odd digit run encodes its first digit in set B.
+        values.push(usize::from(text[i] - 32));
+        i += 1;
+    }
+    let check = values.iter().enumerate().map(|(i, &v)| i.max(1) * v).sum::<usize>() % 103;
+    values.push(check);
+    values.push(CODE128_STOP);
+    values
+}
+
+fn ean13_check(digits: &[u8]) -> u8 {
+    let sum: u32 = digits.iter().enumerate().map(|(i, &d)| u32::from(d) * if i % 2 == 0 { 1 } else { 3 }).sum();
+    ((10 - sum % 10) % 10) as u8
+}
+
+// Bar/space widths of each Code 128 symbol, starting with a bar.
+const CODE128: [&str; 107] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212", "221213",
+    "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221", "223211", "221132",
+    "221231", "213212", "223112", "312131", "311222", "321122", "321221", "312212", "322112", "322211",
+    "212123", "212321", "232121", "111323", "131123", "131321", "112313", "132113", "132311", "211313",
+    "231113", "231311", "112133", "112331", "132131", "113123", "113321", "133121", "313121", "211331",
+    "231131", "213113", "213311", "213131", "311123", "311321", "331121", "312113", "312311", "332111",
+    "314111", "221411", "431111", "111224", "111422", "121124", "121421", "141122", "141221", "112214",
+    "112412", "122114", "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111",
+    "111242", "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+    "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311", "113141",
+    "114131", "311141", "411131", "211412", "211214", "211232", "2331112",
+];
+
+// EAN-13 left hand digit codes with odd (L) and even (G) parity.
+const EAN_L: [&str; 10] = [
+    "0001101", "0011001", "0010011", "0111101", "0100011", "0110001", "0101111", "0111011", "0110111", "0001011",
+];
+const EAN_G: [&str; 10] = [
+    "0100111", "0110011", "0011011", "0100001", "0011101", "0111001", "0000101", "0010001", "0001001", "0010111",
+];
+
+// Parity of the six left hand digits, selected by the first digit.
+const EAN_PARITY: [&str; 10] = [
+    "LLLLLL", "LLGLGG", "LLGGLG", "LLGGGL", "LGLLGG", "LGGLLG", "LGGGLL", "LGLGLG", "LGLGGL", "LGGLGL",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::mock_display::MockDisplay;
+
+    #[test]
+    fn code128_tables() {
+        for (value, widths) in CODE128.iter().enumerate() {
+            let modules: u32 = widths.bytes().map(|b| u32::from(b - b'0')).sum();
+            assert_eq!(modules, if value == CODE128_STOP { 13 } else { 11 }, "symbol {}", value);
+        }
+    }
+
+    #[test]
+    fn code128_encoding() {
+        assert_eq!(code128_values(b"PJJ123C"), vec![104, 48, 42, 42, 17, 18, 19, 35, 55, 106]);
+        assert_eq!(code128_values(b"123456"), vec![105, 12, 34, 56, 44, 106]);
+        // Switch to set C for a long run of digits, starting it in set B if odd.
+        let values = code128_values(b"AB1234567");
+        assert_eq!(&values[..6], &[104, 33, 34, 17, 99, 23]);
+        assert!(Barcode::code128("café").is_err());
+        let barcode = Barcode::code128("PJJ123C").expect("encode");
+        assert_eq!(barcode.width(), 11 * 9 + 13 + 20);
+    }
+
+    #[test]
+    fn ean13_encoding() {
+        assert_eq!(ean13_check(&[4, 0, 0, 6, 3, 8, 1, 3, 3, 3, 9, 3]), 1);
+        let barcode = Barcode::ean13("400638133393").expect("encode");
+        assert_eq!(barcode, Barcode::ean13("4006381333931").expect("encode"));
+        assert_eq!(barcode.width(), 95 + 18);
+        assert!(Barcode::ean13("4006381333932").is_err());
+        assert!(Barcode::ean13("40063813339").is_err());
+        assert!(Barcode::ean13("40063813339x").is_err());
+        // Guard bars, then 4's first L-parity 0.
+        assert_eq!(&barcode.modules[..10], &[true, false, true, false, false, false, true, true, false, true]);
+    }
+
+    #[test]
+    fn qr_autofit() {
+        let qr = Qr::new(b"https://example.com", EccLevel::Medium).expect("encode");
+        assert_eq!(qr.width(), 25);
+        assert!(Qr::new(b"https://example.com", EccLevel::High).expect("encode").width() > 25);
+        let mut display = MockDisplay::<BinaryColor>::new();
+        display.set_allow_overdraw(true);
+        qr.draw(&mut display).expect("draw");
+        // 33 modules including the quiet zone at 1 pixel each, centred.
+        let dark = MockDisplay::from_points(
+            display.bounding_box().points().filter(|&p| display.get_pixel(p) == Some(BinaryColor::On)), BinaryColor::On
+        ).affected_area();
+        assert_eq!(dark, Rectangle::new(Point::new(19, 19), Size::new(25, 25)));
+        assert_eq!(qr.module_size(Rectangle::new(Point::zero(), Size::new(66, 66))), Some(2));
+        assert_eq!(qr.module_size(Rectangle::new(Point::zero(), Size::new(65, 65))), Some(1));
+        assert_eq!(qr.module_size(Rectangle::new(Point::zero(), Size::new(26, 100))), None);
+    }
+
+    #[test]
+    fn barcode_modules_are_whole_pixels() {
+        let barcode = Barcode::ean13("400638133393").expect("encode").fit(Rectangle::new(Point::new(0, 10), Size::new(250, 40)));
+        assert_eq!(barcode.module_size(Rectangle::new(Point::zero(), Size::new(250, 40))), Some(2));
+        let mut display = MockDisplay::<BinaryColor>::new();
+        display.set_allow_out_of_bounds_drawing(true);
+        display.set_allow_overdraw(true);
+        barcode.draw(&mut display).expect("draw");
+        // Quiet zone then the first guard bar two pixels wide, full height.
+        let left = (250 - 113 * 2) / 2 + 11 * 2;
+        assert_eq!(display.get_pixel(Point::new(left - 1, 20)), Some(BinaryColor::Off));
+        assert_eq!(display.get_pixel(Point::new(left, 10)), Some(BinaryColor::On));
+        assert_eq!(display.get_pixel(Point::new(left + 1, 49)), Some(BinaryColor::On));
+        assert_eq!(display.get_pixel(Point::new(left + 2, 20)), Some(BinaryColor::Off));
+    }
+}
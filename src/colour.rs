@@ -0,0 +1,84 @@
+// Panel and border colours.
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+
+#[allow(dead_code)]
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Colour {
+    White,
+    Black,
+    Red,
+    Yellow,
+    /// Red panel intended for high-temperature use. There is no measured
+    /// waveform for it, so it is driven exactly as `Red`.
+    RedHt,
+    /// Black panel driven with a 4 grey level waveform, see `Inky1608::grey_layer()`.
+    /// Experimental: the waveform hasn't been measured on a panel.
+    Gray2,
+}
+
+impl fmt::Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Colour::White => "white",
+            Colour::Black => "black",
+            Colour::Red => "red",
+            Colour::Yellow => "yellow",
+            Colour::RedHt => "red-ht",
+            Colour::Gray2 => "gray2",
+        })
+    }
+}
+
+impl FromStr for Colour {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "white" => Ok(Colour::White),
+            "black" => Ok(Colour::Black),
+            "red" => Ok(Colour::Red),
+            "yellow" => Ok(Colour::Yellow),
+            "red-ht" | "redht" => Ok(Colour::RedHt),
+            "gray2" | "grey2" => Ok(Colour::Gray2),
+            _ => Err(Error::new(ErrorKind::InvalidInput, format!("invalid colour \"{}\"", s))),
+        }
+    }
+}
+
+/// Convert the colour byte stored in the display's EEPROM.
+impl TryFrom<u8> for Colour {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Colour::Black),
+            2 => Ok(Colour::Red),
+            3 => Ok(Colour::Yellow),
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("unsupported EEPROM colour {}", value))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colour_strings() {
+        for colour in [Colour::White, Colour::Black, Colour::Red, Colour::Yellow, Colour::RedHt, Colour::Gray2] {
+            assert_eq!(colour.to_string().parse::<Colour>().expect("parse"), colour);
+        }
+        assert_eq!("Red".parse::<Colour>().expect("parse"), Colour::Red);
+        assert!("purple".parse::<Colour>().is_err());
+    }
+
+    #[test]
+    fn colour_from_eeprom() {
+        assert_eq!(Colour::try_from(1).expect("black"), Colour::Black);
+        assert_eq!(Colour::try_from(3).expect("yellow"), Colour::Yellow);
+        assert!(Colour::try_from(0).is_err());
+        assert!(Colour::try_from(5).is_err());
+    }
+}
@@ -0,0 +1,561 @@
+// A display daemon, so several processes can share one panel, and its client.
+//
+// The daemon owns the `Inky1608` and listens on a Unix domain socket. Each
+// request is one line of JSON, e.g.
+//
+//     {"command":"frame","width":250,"height":122,"partial":false,"accent":false}
+//
+// followed, for frames, by the pixels packed eight to a byte, row by row,
+// most significant bit first, a set bit for black (then the same for the
+// accent colour if "accent" is true). Other commands are "clear", "border"
+// (with "colour": a colour name or "floating"), "sleep" and "info". Every
+// request gets one line of JSON back: {"ok":true}, with "info" for info
+// requests, or {"ok":false,"error":"..."}.
+// A frame whose size doesn't match the display, or a request line longer than
+// 4KiB, is refused unread, and the connection closed.
+//
+// The socket is created readable and writable by every user, as the daemon
+// often runs as root for the GPIOs while its clients don't. Restrict it with
+// `Server::set_mode()` (`inkyd --socket-mode`).
+//
+// Requests are applied one at a time by a single display thread, waiting for
+// each refresh to finish. Frames that arrive while the panel is busy replace
+// any frame still waiting, so only the latest is shown.
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write as _};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Condvar};
+use std::thread;
+
+/// Where the daemon listens unless told otherwise.
+pub const DEFAULT_SOCKET: &str = "/tmp/inkyd.sock";
+
+/// Permissions the socket is created with: any user may connect.
+pub const DEFAULT_SOCKET_MODE: u32 = 0o666;
+
+// Longer request lines are refused.
+const MAX_REQUEST: u64 = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+enum Request {
+    Frame { width: u32, height: u32, #[serde(default)] partial: bool, #[serde(default)] accent: bool },
+    Clear,
+    Border { colour: String },
+    Sleep,
+    Info,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Response {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    info: Option<String>,
+}
+
+/// An image to send to the daemon, the size of the display. Draw on it with
+/// embedded-graphics, and use `accent_layer()` for red/yellow pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    size: Size,
+    black: Vec<bool>,
+    accent: Vec<bool>,
+}
+
+impl Frame {
+    /// A white frame.
+    pub fn new(size: Size) -> Frame {
+        let pixels = (size.width * size.height) as usize;
+        Frame { size, black: vec![false; pixels], accent: vec![false; pixels] }
+    }
+
+    /// Drawing target for the accent colour, as `Inky1608::accent_layer()`.
+    pub fn accent_layer(&mut self) -> FrameAccent<'_> {
+        FrameAccent { frame: self }
+    }
+
+    fn index(&self, point: Point) -> Option<usize> {
+        if point.x >= 0 && point.y >= 0 && (point.x as u32) < self.size.width && (point.y as u32) < self.size.height {
+            Some(point.y as usize * self.size.width as usize + point.x as usize)
+        } else {
+            None
+        }
+    }
+
+    fn has_accent(&self) -> bool {
+        self.accent.contains(&true)
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        let mut payload = pack_bits(&self.black);
+        if self.has_accent() {
+            payload.extend(pack_bits(&self.accent));
+        }
+        payload
+    }
+}
+
+impl DrawTarget for Frame {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, colour) in pixels {
+            if let Some(i) = self.index(point) {
+                self.black[i] = colour.is_on();
+                self.accent[i] = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for Frame {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+/// The accent colour plane of a `Frame`.
+pub struct FrameAccent<'a> {
+    frame: &'a mut Frame,
+}
+
+impl DrawTarget for FrameAccent<'_> {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, colour) in pixels {
+            if let Some(i) = self.frame.index(point) {
+                self.frame.accent[i] = colour.is_on();
+                self.frame.black[i] = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for FrameAccent<'_> {
+    fn size(&self) -> Size {
+        self.frame.size
+    }
+}
+
+/// A connection to the daemon.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    /// Connect to a daemon listening on `path`, e.g. `DEFAULT_SOCKET`.
+    pub fn connect<P: AsRef<Path>>(path: P) -> Result<Client, Error> {
+        let writer = UnixStream::connect(path)?;
+        Ok(Client { reader: BufReader::new(writer.try_clone()?), writer })
+    }
+
+    /// Queue a frame to be shown, with a partial refresh if `partial` (black
+    /// panels only). Returns once the daemon has accepted it.
+    pub fn show(&mut self, frame: &Frame, partial: bool) -> Result<(), Error> {
+        let request = Request::Frame { width: frame.size.width, height: frame.size.height, partial, accent: frame.has_accent() };
+        self.request(&request, &frame.payload()).map(|_| ())
+    }
+
+    /// Clear the display to white.
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.request(&Request::Clear, &[]).map(|_| ())
+    }
+
+    /// Set and show the border colour; None leaves it floating.
+    pub fn set_border(&mut self, colour: Option<Colour>) -> Result<(), Error> {
+        let colour = colour.map_or("floating".to_string(), |c| c.to_string());
+        self.request(&Request::Border { colour }, &[]).map(|_| ())
+    }
+
+    /// Put the panel into deep sleep until the next request.
+    pub fn sleep(&mut self) -> Result<(), Error> {
+        self.request(&Request::Sleep, &[]).map(|_| ())
+    }
+
+    /// Description of the display, as printed by `inky info`.
+    pub fn info(&mut self) -> Result<String, Error> {
+        self.request(&Request::Info, &[]).map(|info| info.unwrap_or_default())
+    }
+
+    fn request(&mut self, request: &Request, payload: &[u8]) -> Result<Option<String>, Error> {
+        let mut line = serde_json::to_vec(request).map_err(Error::other)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        self.writer.write_all(payload)?;
+        let mut reply = String::new();
+        if self.reader.read_line(&mut reply)? == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "daemon closed the connection"));
+        }
+        let response: Response = serde_json::from_str(&reply).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        if response.ok {
+            Ok(response.info)
+        } else {
+            Err(Error::other(response.error.unwrap_or_default()))
+        }
+    }
+}
+
+// Work for the display thread.
+enum Job {
+    Frame { frame: Frame, partial: bool },
+    Clear,
+    Border(Option<Colour>),
+    Sleep,
+    Info(mpsc::Sender<String>),
+}
+
+impl Job {
+    // Whether the job replaces the whole image, so any earlier one waiting can be dropped.
+    fn replaces_image(&self) -> bool {
+        matches!(self, Job::Frame { .. } | Job::Clear)
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: Mutex<VecDeque<Job>>,
+    ready: Condvar,
+}
+
+impl Queue {
+    fn push(&self, job: Job) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if job.replaces_image() {
+            jobs.retain(|waiting| !waiting.replaces_image());
+        }
+        jobs.push_back(job);
+        self.ready.notify_one();
+    }
+
+    fn pop(&self) -> Job {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            match jobs.pop_front() {
+                Some(job) => return job,
+                None => jobs = self.ready.wait(jobs).unwrap_or_else(|e| e.into_inner())
+            }
+        }
+    }
+}
+
+/// The daemon: owns the display and serves clients on a Unix socket.
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+    inky: Inky1608,
+}
+
+impl Server {
+    /// Listen on `path`, replacing a stale socket left by a previous run. The
+    /// socket's mode is set to `DEFAULT_SOCKET_MODE`.
+    pub fn bind<P: AsRef<Path>>(path: P, inky: Inky1608) -> Result<Server, Error> {
+        let path = path.as_ref();
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(Error::new(ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path.display())));
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Err(Error::new(ErrorKind::AddrInUse, format!("a daemon is already listening on {}", path.display())));
+            }
+            std::fs::remove_file(path)?;
+        }
+        let server = Server { listener: UnixListener::bind(path)?, path: path.to_path_buf(), inky };
+        server.set_mode(DEFAULT_SOCKET_MODE)?;
+        Ok(server)
+    }
+
+    /// Change the socket's permissions, e.g. 0o660 to only let the daemon's
+    /// group connect.
+    pub fn set_mode(&self, mode: u32) -> Result<(), Error> {
+        std::fs::set_permissions(&self.path, std::fs::Permissions::from_mode(mode))
+    }
+
+    /// Serve clients until the listener fails.
+    pub fn run(self) -> Result<(), Error> {
+        let queue = Arc::new(Queue::default());
+        let size = self.inky.size();
+        let colour = self.inky.colour;
+        let display_queue = Arc::clone(&queue);
+        let mut inky = self.inky;
+        thread::spawn(move || loop {
+            if let Err(e) = apply(&mut inky, display_queue.pop()) {
+                eprintln!("inkyd: {}", e);
+            }
+        });
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || {
+                if let Err(e) = serve(stream, &queue, size, colour) {
+                    eprintln!("inkyd: client: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+// Handle one client's requests until it disconnects.
+fn serve(stream: UnixStream, queue: &Queue, size: Size, colour: Colour) -> Result<(), Error> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let (info_sender, info) = mpsc::channel();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = (&mut reader).take(MAX_REQUEST).read_line(&mut line)?;
+        if read == 0 {
+            return Ok(());
+        }
+        if read as u64 == MAX_REQUEST && !line.ends_with('\n') {
+            let error = format!("request line is longer than {} bytes", MAX_REQUEST);
+            return reply(&mut writer, &Response { error: Some(error), ..Response::default() });
+        }
+        let response = match serde_json::from_str(&line) {
+            Ok(request) => match job(request, &mut reader, size, colour, &info_sender) {
+                Ok(job) => {
+                    let answers = matches!(job, Job::Info(_));
+                    queue.push(job);
+                    let info = if answers { info.recv().ok() } else { None };
+                    Response { ok: true, info, ..Response::default() }
+                }
+                Err(e) if e.kind() == ErrorKind::InvalidInput => Response { error: Some(e.to_string()), ..Response::default() },
+                // The payload wasn't read, so say why before hanging up.
+                Err(e) if e.kind() == ErrorKind::InvalidData => {
+                    let response = Response { error: Some(e.to_string()), ..Response::default() };
+                    return reply(&mut writer, &response);
+                }
+                // The stream is out of step, e.g. a short payload.
+                Err(e) => return Err(e),
+            },
+            Err(e) => Response { error: Some(format!("bad request: {}", e)), ..Response::default() },
+        };
+        reply(&mut writer, &response)?;
+    }
+}
+
+fn reply(writer: &mut UnixStream, response: &Response) -> Result<(), Error> {
+    let mut line = serde_json::to_vec(response).map_err(Error::other)?;
+    line.push(b'\n');
+    writer.write_all(&line)
+}
+
+// Turn a request into a job, reading any payload that follows it. Info jobs
+// answer on `info`.
+fn job<R: Read>(request: Request, reader: &mut R, size: Size, colour: Colour, info: &mpsc::Sender<String>) -> Result<Job, Error> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidInput, message);
+    Ok(match request {
+        Request::Frame { width, height, partial, accent } => {
+            // Check the size before allocating or reading anything. The payload
+            // is left unread, so the caller has to drop the connection.
+            if Size::new(width, height) != size {
+                return Err(Error::new(ErrorKind::InvalidData,
+                    format!("frame is {}x{} but the display is {}x{}", width, height, size.width, size.height)));
+            }
+            let mut frame = Frame::new(size);
+            let plane = frame.black.len().div_ceil(8);
+            let mut payload = vec![0; if accent { plane * 2 } else { plane }];
+            reader.read_exact(&mut payload)?;
+            if partial && colour != Colour::Black {
+                return Err(invalid(format!("a {} panel can't do partial refreshes", colour)));
+            }
+            if accent && colour == Colour::Black {
+                return Err(invalid("a black panel has no accent colour".to_string()));
+            }
+            unpack_bits(&payload[..plane], &mut frame.black);
+            if accent {
+                unpack_bits(&payload[plane..], &mut frame.accent);
+            }
+            Job::Frame { frame, partial }
+        }
+        Request::Clear => Job::Clear,
+        Request::Border { colour: border } => match border.as_str() {
+            "floating" => Job::Border(None),
+            name => {
+                let border: Colour = name.parse()?;
+                if !border_supported(colour, border) {
+                    return Err(invalid(format!("a {} panel can't show a {} border", colour, border)));
+                }
+                Job::Border(Some(border))
+            }
+        },
+        Request::Sleep => Job::Sleep,
+        Request::Info => Job::Info(info.clone()),
+    })
+}
+
+// Carry out a job on the display, waiting for any refresh to finish.
+fn apply(inky: &mut Inky1608, job: Job) -> Result<(), Error> {
+    let gpio = |e: linux_embedded_hal::sysfs_gpio::Error| Error::other(e.to_string());
+    match job {
+        Job::Frame { frame, partial } => {
+            let points = frame.bounding_box().points();
+            let _ = inky.draw_iter(points.clone().zip(&frame.black).map(|(p, &black)| Pixel(p, BinaryColor::from(black))));
+            let _ = inky.accent_layer().draw_iter(points.zip(&frame.accent).filter(|(_, &a)| a).map(|(p, _)| Pixel(p, BinaryColor::On)));
+            if partial { inky.flush_partial() } else { inky.flush() }.map_err(gpio)?;
+        }
+        Job::Clear => {
+            let _ = inky.clear(BinaryColor::Off);
+            inky.flush().map_err(gpio)?;
+        }
+        Job::Border(colour) => {
+            match colour {
+                Some(colour) => inky.set_border(colour)?,
+                None => inky.set_border_floating(),
+            }
+            inky.refresh_border().map_err(gpio)?;
+        }
+        Job::Sleep => inky.sleep().map_err(gpio)?,
+        Job::Info(reply) => {
+            let _ = reply.send(inky.to_string());
+        }
+    }
+    inky.wait_refresh().map_err(gpio)
+}
+
+// Inverse of pack_bits.
+fn unpack_bits(bytes: &[u8], bits: &mut [bool]) {
+    for (i, bit) in bits.iter_mut().enumerate() {
+        *bit = bytes[i / 8] & (0x80 >> (i % 8)) != 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+
+    const SIZE: Size = Size::new(12, 4);
+
+    fn frame() -> Frame {
+        let mut frame = Frame::new(SIZE);
+        Rectangle::new(Point::new(1, 1), Size::new(3, 2))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(&mut frame).expect("draw");
+        frame
+    }
+
+    #[test]
+    fn payload_round_trip() {
+        let mut frame = frame();
+        Pixel(Point::new(11, 3), BinaryColor::On).draw(&mut frame.accent_layer()).expect("draw");
+        let payload = frame.payload();
+        assert_eq!(payload.len(), 12);
+        let (info, _) = mpsc::channel();
+        let request = Request::Frame { width: 12, height: 4, partial: false, accent: true };
+        match job(request, &mut payload.as_slice(), SIZE, Colour::Red, &info) {
+            Ok(Job::Frame { frame: received, .. }) => assert_eq!(received, frame),
+            _ => panic!("expected a frame"),
+        }
+    }
+
+    #[test]
+    fn request_validation() {
+        let (info, _) = mpsc::channel();
+        assert!(matches!(job(Request::Info, &mut [0u8; 0].as_slice(), SIZE, Colour::Black, &info), Ok(Job::Info(_))));
+        let wrong_size = Request::Frame { width: 8, height: 4, partial: false, accent: false };
+        let error = job(wrong_size, &mut [0u8; 4].as_slice(), SIZE, Colour::Black, &info).err().expect("wrong size");
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let short = Request::Frame { width: 12, height: 4, partial: false, accent: false };
+        let error = job(short, &mut [0u8; 2].as_slice(), SIZE, Colour::Black, &info).err().expect("short payload");
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        let partial = Request::Frame { width: 12, height: 4, partial: true, accent: false };
+        let error = job(partial, &mut [0u8; 6].as_slice(), SIZE, Colour::Red, &info).err().expect("partial on red");
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let accent = Request::Frame { width: 12, height: 4, partial: false, accent: true };
+        let error = job(accent, &mut [0u8; 12].as_slice(), SIZE, Colour::Black, &info).err().expect("accent on black");
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let red_border = Request::Border { colour: "red".to_string() };
+        assert!(job(red_border, &mut [0u8; 0].as_slice(), SIZE, Colour::Black, &info).is_err());
+        let json = serde_json::to_string(&Request::Border { colour: "floating".to_string() }).expect("json");
+        assert_eq!(json, r#"{"command":"border","colour":"floating"}"#);
+    }
+
+    #[test]
+    fn frames_coalesce() {
+        let queue = Queue::default();
+        queue.push(Job::Frame { frame: Frame::new(SIZE), partial: false });
+        queue.push(Job::Sleep);
+        queue.push(Job::Clear);
+        queue.push(Job::Frame { frame: frame(), partial: true });
+        assert!(matches!(queue.pop(), Job::Sleep));
+        assert!(matches!(queue.pop(), Job::Frame { partial: true, .. }));
+        assert!(queue.jobs.lock().expect("lock").is_empty());
+    }
+
+    #[test]
+    fn serve_replies() {
+        let (mut client, server) = UnixStream::pair().expect("socket pair");
+        let queue = Arc::new(Queue::default());
+        let server_queue = Arc::clone(&queue);
+        let handle = thread::spawn(move || serve(server, &server_queue, SIZE, Colour::Black));
+        let mut replies = BufReader::new(client.try_clone().expect("clone"));
+        let mut reply = String::new();
+
+        client.write_all(b"{\"command\":\"clear\"}\n").expect("write");
+        replies.read_line(&mut reply).expect("read");
+        assert_eq!(reply, "{\"ok\":true}\n");
+        assert!(matches!(queue.pop(), Job::Clear));
+
+        reply.clear();
+        client.write_all(b"{\"command\":\"info\"}\n").expect("write");
+        match queue.pop() {
+            Job::Info(sender) => sender.send("inky".to_string()).expect("send"),
+            _ => panic!("expected info"),
+        }
+        replies.read_line(&mut reply).expect("read");
+        assert_eq!(reply, "{\"ok\":true,\"info\":\"inky\"}\n");
+
+        reply.clear();
+        client.write_all(b"{\"command\":\"dance\"}\n").expect("write");
+        replies.read_line(&mut reply).expect("read");
+        assert!(reply.starts_with("{\"ok\":false,\"error\":\"bad request"), "{}", reply);
+
+        // A frame of the wrong size is refused without reading its payload,
+        // and the connection is closed.
+        reply.clear();
+        client.write_all(b"{\"command\":\"frame\",\"width\":65535,\"height\":65535,\"partial\":false,\"accent\":true}\n").expect("write");
+        replies.read_line(&mut reply).expect("read");
+        assert!(reply.contains("frame is 65535x65535"), "{}", reply);
+        reply.clear();
+        assert_eq!(replies.read_line(&mut reply).expect("read"), 0);
+
+        drop(client);
+        drop(replies);
+        handle.join().expect("join").expect("serve");
+    }
+
+    #[test]
+    fn long_requests_refused() {
+        let (mut client, server) = UnixStream::pair().expect("socket pair");
+        let queue = Arc::new(Queue::default());
+        let handle = thread::spawn(move || serve(server, &queue, SIZE, Colour::Black));
+        // No newline: without a limit this would be buffered forever.
+        client.write_all(&[b'a'; MAX_REQUEST as usize + 1]).expect("write");
+        let mut replies = BufReader::new(client);
+        let mut reply = String::new();
+        replies.read_line(&mut reply).expect("read");
+        assert!(reply.contains("longer than 4096 bytes"), "{}", reply);
+        reply.clear();
+        assert_eq!(replies.read_line(&mut reply).expect("read"), 0);
+        handle.join().expect("join").expect("serve");
+    }
+}
@@ -0,0 +1,155 @@
+// Hardware self-test, to narrow down which part of a misbehaving display is at fault.
+use super::*;
+use std::fmt;
+
+// How long to wait for BUSY before deciding it is stuck.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Results of `Inky1608::diagnose()`.
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    /// Summary of the EEPROM record, as read during the self-test.
+    pub eeprom: String,
+    /// Problems found in the EEPROM record (see `EEPType::problems`), or
+    /// that it couldn't be read.
+    pub eeprom_problems: Vec<String>,
+    /// Whether BUSY was low (idle) before the controller was reset.
+    pub busy_idle: bool,
+    /// Time for BUSY to clear after pulsing RESET, or None if it stayed high.
+    pub reset_response: Option<Duration>,
+    /// Time for BUSY to clear after a soft reset command, or None if it stayed high.
+    pub soft_reset_response: Option<Duration>,
+    /// Whether reading the OTP registers returned plausible data. A missing or
+    /// miswired data line reads back as all 0x00 or all 0xFF.
+    pub spi_readback: bool,
+    /// Controller temperature sensor reading in degrees C, if one was obtained.
+    pub temperature: Option<f32>,
+}
+
+impl Diagnostics {
+    /// Whether every check passed.
+    pub fn healthy(&self) -> bool {
+        self.eeprom_problems.is_empty()
+            && self.busy_idle
+            && self.reset_response.is_some()
+            && self.soft_reset_response.is_some()
+            && self.spi_readback
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn response(d: Option<Duration>) -> String {
+            match d {
+                Some(d) => format!("{}ms", d.as_millis()),
+                None => "no response (BUSY stuck high)".to_string(),
+            }
+        }
+        writeln!(f, "EEPROM: {}", self.eeprom.replace('\n', ", "))?;
+        if self.eeprom_problems.is_empty() {
+            writeln!(f, "EEPROM check: ok")?;
+        } else {
+            writeln!(f, "EEPROM check: {}", self.eeprom_problems.join("; "))?;
+        }
+        writeln!(f, "BUSY idle before reset: {}", if self.busy_idle { "yes" } else { "no" })?;
+        writeln!(f, "RESET response: {}", response(self.reset_response))?;
+        writeln!(f, "Soft reset response: {}", response(self.soft_reset_response))?;
+        writeln!(f, "SPI readback: {}", if self.spi_readback { "ok" } else { "failed" })?;
+        match self.temperature {
+            Some(t) => writeln!(f, "Temperature: {:.1}C", t)?,
+            None => writeln!(f, "Temperature: unavailable")?,
+        }
+        write!(f, "Overall: {}", if self.healthy() { "healthy" } else { "FAULT" })
+    }
+}
+
+impl Inky1608 {
+    /// Run a hardware self-test. This resets the controller, so the next flush
+    /// reinitialises it; the image on the panel is left alone.
+    #[allow(dead_code)]
+    pub fn diagnose(&mut self) -> Result<Diagnostics, linux_embedded_hal::sysfs_gpio::Error> {
+        self.wait_refresh()?;
+        if !self.exported {
+            export_pins(&self.dc_pin, &self.reset_pin, &self.busy_pin)?;
+            self.exported = true;
+        }
+        self.initialised = false;
+
+        let busy_idle = self.busy_pin.get_value()? == 0;
+
+        let delay = Duration::new(0, 500_000);
+        self.reset_pin.set_value(0)?;
+        sleep(delay);
+        self.reset_pin.set_value(1)?;
+        let reset_response = self.busy_wait_timeout(BUSY_TIMEOUT)?;
+
+        self.send_command(SW_RESET, None)?;
+        let soft_reset_response = self.busy_wait_timeout(BUSY_TIMEOUT)?;
+
+        let spi_readback = match self.read_command(READ_OTP, readback::OTP_LEN) {
+            Ok(otp) => !otp.iter().all(|b| *b == 0x00) && !otp.iter().all(|b| *b == 0xff),
+            Err(_) => false,
+        };
+
+        // Have the controller load a reading from its temperature sensor.
+        self.send_command(DISP_CTRL2, Some(&[0xB1]))?;
+        self.send_command(MASTER_ACTIVATE, None)?;
+        let temperature = match self.busy_wait_timeout(BUSY_TIMEOUT)? {
+            Some(_) => self.read_command(TEMP_READ, 2).ok().map(|t| decode_temperature(t[0], t[1])),
+            None => None,
+        };
+
+        // Read the EEPROM afresh: the HAT may have gone missing or failed
+        // since the display was opened.
+        let (eeprom, eeprom_problems) = match self.eeprom.probe() {
+            Ok(record) => (record.to_string(), record.problems()),
+            Err(e) => ("unreadable".to_string(), vec![format!("EEPROM read failed: {}", e)]),
+        };
+
+        Ok(Diagnostics {
+            eeprom,
+            eeprom_problems,
+            busy_idle,
+            reset_response,
+            soft_reset_response,
+            spi_readback,
+            temperature,
+        })
+    }
+}
+
+// The temperature register is a 12 bit two's complement value in 1/16 C steps,
+// left aligned in two bytes.
+fn decode_temperature(msb: u8, lsb: u8) -> f32 {
+    f32::from(i16::from_be_bytes([msb, lsb]) >> 4) / 16.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temperature() {
+        assert_eq!(decode_temperature(0x19, 0x00), 25.0);
+        assert_eq!(decode_temperature(0x00, 0x80), 0.5);
+        assert_eq!(decode_temperature(0xFF, 0x00), -1.0);
+    }
+
+    #[test]
+    fn report() {
+        let mut diagnostics = Diagnostics {
+            eeprom: "Black pHAT (SSD1608)\nDisplay: 250x122\nColour: black".to_string(),
+            eeprom_problems: vec![],
+            busy_idle: true,
+            reset_response: Some(Duration::from_millis(2)),
+            soft_reset_response: Some(Duration::from_millis(3)),
+            spi_readback: true,
+            temperature: Some(21.5),
+        };
+        assert!(diagnostics.healthy());
+        assert!(diagnostics.to_string().ends_with("Overall: healthy"));
+        diagnostics.soft_reset_response = None;
+        assert!(!diagnostics.healthy());
+        assert!(diagnostics.to_string().contains("Soft reset response: no response"));
+    }
+}
@@ -0,0 +1,349 @@
+// Dithering RGB pixels down to the handful of colours a panel can show.
+use super::*;
+use std::sync::OnceLock;
+
+/// How pixels are reduced to the panel's colours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    /// Nearest colour, no dithering. Best for line art and text.
+    #[default]
+    None,
+    /// Floyd–Steinberg error diffusion.
+    FloydSteinberg,
+    /// Atkinson error diffusion: only 3/4 of the error is spread, giving
+    /// more contrast and cleaner highlights than Floyd–Steinberg.
+    Atkinson,
+    /// Ordered dithering with a 4x4 Bayer matrix.
+    Bayer4,
+    /// Ordered dithering with an 8x8 Bayer matrix.
+    Bayer8,
+    /// Ordered dithering with a 32x32 blue noise threshold map, which avoids
+    /// the cross-hatch pattern of Bayer matrices.
+    BlueNoise,
+}
+
+impl std::str::FromStr for Dither {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Dither::None),
+            "floyd-steinberg" => Ok(Dither::FloydSteinberg),
+            "atkinson" => Ok(Dither::Atkinson),
+            "bayer4" => Ok(Dither::Bayer4),
+            "bayer8" => Ok(Dither::Bayer8),
+            "blue-noise" => Ok(Dither::BlueNoise),
+            _ => Err(Error::new(ErrorKind::InvalidInput, format!("unknown dither '{}'", s)))
+        }
+    }
+}
+
+/// Reduce `width`-wide rows of RGB `pixels` to the colours the `panel` can
+/// show: white, black and, on red/yellow panels, the accent colour. For
+/// greyscale input pass equal channels. Gray2 panels only get black and white
+/// here; see `dither_grey()` for their grey levels.
+pub fn dither(pixels: &[[u8; 3]], width: usize, method: Dither, panel: Colour) -> Vec<Colour> {
+    reduce(pixels, width, method, &palette(panel), 255.0)
+}
+
+/// As `dither()` for a `Colour::Gray2` panel, reducing the pixels to its four
+/// grey levels.
+pub fn dither_grey(pixels: &[[u8; 3]], width: usize, method: Dither) -> Vec<Gray2> {
+    let palette: Vec<(Gray2, [f32; 3])> = (0..4)
+        .map(|luma| (Gray2::new(luma), [f32::from(luma) * GREY_STEP; 3]))
+        .collect();
+    reduce(pixels, width, method, &palette, GREY_STEP)
+}
+
+// Distance between neighbouring grey levels: 0x00, 0x55, 0xaa and 0xff.
+const GREY_STEP: f32 = 85.0;
+
+// Reduce the pixels to the palette. Ordered dithering spreads its thresholds
+// over `step`, the distance between neighbouring palette values.
+fn reduce<T: Copy>(pixels: &[[u8; 3]], width: usize, method: Dither, palette: &[(T, [f32; 3])], step: f32) -> Vec<T> {
+    match method {
+        Dither::None => pixels.iter().map(|&rgb| nearest(palette, rgb.map(f32::from)).0).collect(),
+        Dither::FloydSteinberg => diffuse(pixels, width, palette, &FLOYD_STEINBERG),
+        Dither::Atkinson => diffuse(pixels, width, palette, &ATKINSON),
+        Dither::Bayer4 => ordered(pixels, width, palette, step, 4, |x, y| bayer(x, y, 2)),
+        Dither::Bayer8 => ordered(pixels, width, palette, step, 8, |x, y| bayer(x, y, 3)),
+        Dither::BlueNoise => {
+            let map = blue_noise();
+            ordered(pixels, width, palette, step, BLUE_NOISE_SIZE, |x, y| map[y * BLUE_NOISE_SIZE + x])
+        }
+    }
+}
+
+// The colours a panel can show and their approximate appearance.
+fn palette(panel: Colour) -> Vec<(Colour, [f32; 3])> {
+    let mut palette = vec![(Colour::White, [255.0, 255.0, 255.0]), (Colour::Black, [0.0, 0.0, 0.0])];
+    match panel {
+        Colour::Red | Colour::RedHt => palette.push((Colour::Red, [255.0, 0.0, 0.0])),
+        Colour::Yellow => palette.push((Colour::Yellow, [255.0, 255.0, 0.0])),
+        _ => ()
+    }
+    palette
+}
+
+// The palette entry closest to rgb, and that entry's value. Palettes are
+// never empty.
+fn nearest<T: Copy>(palette: &[(T, [f32; 3])], rgb: [f32; 3]) -> (T, [f32; 3]) {
+    let distance = |c: &[f32; 3]| -> f32 {
+        c.iter().zip(rgb.iter()).map(|(a, b)| (a - b) * (a - b)).sum()
+    };
+    palette[1..].iter().fold(palette[0], |best, entry| {
+        if distance(&entry.1) < distance(&best.1) { *entry } else { best }
+    })
+}
+
+// Error diffusion kernels: (dx, dy, weight).
+const FLOYD_STEINBERG: [(isize, usize, f32); 4] = [
+    (1, 0, 7.0 / 16.0), (-1, 1, 3.0 / 16.0), (0, 1, 5.0 / 16.0), (1, 1, 1.0 / 16.0)
+];
+const ATKINSON: [(isize, usize, f32); 6] = [
+    (1, 0, 1.0 / 8.0), (2, 0, 1.0 / 8.0), (-1, 1, 1.0 / 8.0), (0, 1, 1.0 / 8.0), (1, 1, 1.0 / 8.0), (0, 2, 1.0 / 8.0)
+];
+
+// Each pixel takes the nearest palette colour and the difference is carried
+// on to its unvisited neighbours, per channel, so a three-colour palette
+// diffuses between white, black and the accent.
+fn diffuse<T: Copy>(pixels: &[[u8; 3]], width: usize, palette: &[(T, [f32; 3])], kernel: &[(isize, usize, f32)]) -> Vec<T> {
+    let height = pixels.len() / width.max(1);
+    let mut values: Vec<[f32; 3]> = pixels.iter().map(|rgb| rgb.map(f32::from)).collect();
+    let mut out = Vec::with_capacity(pixels.len());
+    for y in 0..height {
+        for x in 0..width {
+            let value = values[y * width + x];
+            let (colour, chosen) = nearest(palette, value);
+            out.push(colour);
+            for &(dx, dy, weight) in kernel {
+                let (nx, ny) = (x as isize + dx, y + dy);
+                if nx < 0 || nx as usize >= width || ny >= height {
+                    continue;
+                }
+                let target = &mut values[ny * width + nx as usize];
+                for c in 0..3 {
+                    target[c] += (value[c] - chosen[c]) * weight;
+                }
+            }
+        }
+    }
+    out
+}
+
+// Each pixel is offset by a position dependent threshold from an n x n
+// tiled map (ranks 0..n*n), spread over `step`, before taking the nearest
+// palette colour.
+fn ordered<T, F>(pixels: &[[u8; 3]], width: usize, palette: &[(T, [f32; 3])], step: f32, n: usize, rank: F) -> Vec<T>
+where
+    T: Copy,
+    F: Fn(usize, usize) -> usize,
+{
+    let levels = (n * n) as f32;
+    pixels.iter().enumerate().map(|(i, rgb)| {
+        let (x, y) = (i % width.max(1), i / width.max(1));
+        let offset = ((rank(x % n, y % n) as f32 + 0.5) / levels - 0.5) * step;
+        nearest(palette, rgb.map(|c| f32::from(c) + offset)).0
+    }).collect()
+}
+
+// Rank of x, y in the 2^order square Bayer matrix, built by recursively
+// tiling the 2x2 matrix [0 2; 3 1].
+fn bayer(x: usize, y: usize, order: u32) -> usize {
+    let mut rank = 0;
+    for bit in 0..order {
+        let (xb, yb) = ((x >> bit) & 1, (y >> bit) & 1);
+        rank = rank * 4 + 2 * (xb ^ yb) + yb;
+    }
+    rank
+}
+
+const BLUE_NOISE_SIZE: usize = 32;
+
+// Blue noise threshold map, generated on first use.
+fn blue_noise() -> &'static [usize] {
+    static MAP: OnceLock<Vec<usize>> = OnceLock::new();
+    MAP.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE, 1.5))
+}
+
+// Ulichney's void-and-cluster method: rank every cell of an n x n torus so
+// that each prefix of ranks is as evenly spread as possible.
+fn void_and_cluster(n: usize, sigma: f32) -> Vec<usize> {
+    let cells = n * n;
+    // Gaussian weight by toroidal offset.
+    let kernel: Vec<f32> = (0..cells).map(|i| {
+        let (dx, dy) = ((i % n).min(n - i % n), (i / n).min(n - i / n));
+        (-((dx * dx + dy * dy) as f32) / (2.0 * sigma * sigma)).exp()
+    }).collect();
+    let mut field = Field { n, kernel, ones: vec![false; cells], energy: vec![0.0; cells] };
+
+    // A fixed pseudo-random starting pattern with a tenth of the cells set.
+    let mut seed: u32 = 0x2545_f491;
+    let mut placed = 0;
+    while placed < cells / 10 {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        let cell = seed as usize % cells;
+        if !field.ones[cell] {
+            field.toggle(cell);
+            placed += 1;
+        }
+    }
+    // Move points from the tightest cluster to the largest void until stable.
+    loop {
+        let cluster = field.tightest_cluster();
+        field.toggle(cluster);
+        let void = field.largest_void();
+        if void == cluster {
+            field.toggle(cluster);
+            break;
+        }
+        field.toggle(void);
+    }
+
+    let mut rank = vec![0; cells];
+    let initial = field.clone();
+    // Rank the initial points by removing them tightest cluster first...
+    for r in (0..placed).rev() {
+        let cluster = field.tightest_cluster();
+        field.toggle(cluster);
+        rank[cluster] = r;
+    }
+    // ...then the remaining cells by filling the largest void first.
+    let mut field = initial;
+    for r in placed..cells {
+        let void = field.largest_void();
+        field.toggle(void);
+        rank[void] = r;
+    }
+    rank
+}
+
+#[derive(Clone)]
+struct Field {
+    n: usize,
+    kernel: Vec<f32>,
+    ones: Vec<bool>,
+    // Sum of the kernel over every set cell, at each cell.
+    energy: Vec<f32>,
+}
+
+impl Field {
+    fn toggle(&mut self, cell: usize) {
+        let sign = if self.ones[cell] { -1.0 } else { 1.0 };
+        self.ones[cell] = !self.ones[cell];
+        let n = self.n;
+        let (cx, cy) = (cell % n, cell / n);
+        for (i, energy) in self.energy.iter_mut().enumerate() {
+            let (dx, dy) = ((i % n + n - cx) % n, (i / n + n - cy) % n);
+            *energy += sign * self.kernel[dy * n + dx];
+        }
+    }
+
+    fn tightest_cluster(&self) -> usize {
+        self.extreme(true, |a, b| a > b)
+    }
+
+    fn largest_void(&self) -> usize {
+        self.extreme(false, |a, b| a < b)
+    }
+
+    fn extreme<F: Fn(f32, f32) -> bool>(&self, set: bool, better: F) -> usize {
+        let mut best: Option<usize> = None;
+        for (i, &energy) in self.energy.iter().enumerate() {
+            if self.ones[i] == set && best.is_none_or(|b| better(energy, self.energy[b])) {
+                best = Some(i);
+            }
+        }
+        best.unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Dither; 6] = [
+        Dither::None, Dither::FloydSteinberg, Dither::Atkinson, Dither::Bayer4, Dither::Bayer8, Dither::BlueNoise
+    ];
+
+    fn black_fraction(colours: &[Colour]) -> f32 {
+        colours.iter().filter(|&&c| c == Colour::Black).count() as f32 / colours.len() as f32
+    }
+
+    #[test]
+    fn bayer_matrix() {
+        let first_row: Vec<usize> = (0..4).map(|x| bayer(x, 0, 2)).collect();
+        assert_eq!(first_row, vec![0, 8, 2, 10]);
+        let mut ranks: Vec<usize> = (0..64).map(|i| bayer(i % 8, i / 8, 3)).collect();
+        ranks.sort();
+        assert_eq!(ranks, (0..64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn blue_noise_is_a_permutation() {
+        let mut ranks = blue_noise().to_vec();
+        ranks.sort();
+        assert_eq!(ranks, (0..BLUE_NOISE_SIZE * BLUE_NOISE_SIZE).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn solid_colours_are_preserved() {
+        for method in ALL {
+            let white = dither(&[[255; 3]; 64], 8, method, Colour::Black);
+            assert!(white.iter().all(|&c| c == Colour::White), "{:?}", method);
+            let black = dither(&[[0; 3]; 64], 8, method, Colour::Red);
+            assert!(black.iter().all(|&c| c == Colour::Black), "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn grey_levels() {
+        let grey = vec![[128; 3]; 32 * 32];
+        assert_eq!(black_fraction(&dither(&[[100; 3]; 16], 4, Dither::None, Colour::Black)), 1.0);
+        for method in [Dither::FloydSteinberg, Dither::Bayer4, Dither::Bayer8, Dither::BlueNoise] {
+            let fraction = black_fraction(&dither(&grey, 32, method, Colour::Black));
+            assert!((fraction - 0.5).abs() < 0.05, "{:?} {}", method, fraction);
+        }
+        let dark = vec![[64; 3]; 32 * 32];
+        let fraction = black_fraction(&dither(&dark, 32, Dither::BlueNoise, Colour::Black));
+        assert!((fraction - 0.75).abs() < 0.05, "{}", fraction);
+    }
+
+    #[test]
+    fn three_colour_diffusion() {
+        // Pink is a mix of red and white, with no black.
+        let pink = vec![[255, 128, 128]; 16 * 16];
+        let colours = dither(&pink, 16, Dither::FloydSteinberg, Colour::Red);
+        assert!(colours.contains(&Colour::Red));
+        assert!(colours.contains(&Colour::White));
+        assert!(!colours.contains(&Colour::Black));
+        // A black panel can only show it as black and white.
+        let colours = dither(&pink, 16, Dither::FloydSteinberg, Colour::Black);
+        assert!(!colours.contains(&Colour::Red));
+    }
+
+    #[test]
+    fn four_grey_levels() {
+        let ramp: Vec<[u8; 3]> = (0..=255).map(|v| [v; 3]).collect();
+        let greys = dither_grey(&ramp, 16, Dither::None);
+        for luma in 0..4 {
+            assert!(greys.contains(&Gray2::new(luma)), "{}", luma);
+        }
+        assert_eq!(greys[0x55], Gray2::new(1));
+        // Between the two greys, ordered dithering mixes just those two.
+        let between = vec![[0x80; 3]; 16 * 16];
+        for method in [Dither::Bayer4, Dither::BlueNoise, Dither::FloydSteinberg] {
+            let greys = dither_grey(&between, 16, method);
+            assert!(greys.iter().all(|g| matches!(g.luma(), 1 | 2)), "{:?}", method);
+            assert!(greys.contains(&Gray2::new(1)) && greys.contains(&Gray2::new(2)), "{:?}", method);
+        }
+    }
+
+    #[test]
+    fn dither_from_str() {
+        assert_eq!("Floyd-Steinberg".parse::<Dither>().unwrap(), Dither::FloydSteinberg);
+        assert!("random".parse::<Dither>().is_err());
+    }
+}
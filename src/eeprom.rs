@@ -12,7 +12,8 @@ pub struct EEPType<T: I2CDevice> {
     pub colour: u8,
     pcb_variant: u8,
     pub display_variant: u8,
-    i2cdev: T
+    pub write_time: String,
+    i2cdev: Option<T>
 }
 
 impl<T: I2CDevice> fmt::Display for EEPType<T>
@@ -30,32 +31,83 @@ where
 {
     #[allow(dead_code)]
     pub fn new(mut i2c_dev: T) -> Result<EEPType<T>, LinuxI2CError> {
-        i2c_dev.smbus_write_i2c_block_data(0x00, &[0x00])?;
-        let data = i2c_dev.smbus_read_i2c_block_data(0, 29)?;
-        let eep_type = EEPType {
-            width: (data[0] + (data[1] << 1)).into(),
-            height: (data[2] + (data[3] << 1)).into(),
+        let data = read_record(&mut i2c_dev)?;
+        Ok(EEPType::decode(&data, Some(i2c_dev)))
+    }
+
+    /// Read the EEPROM again, e.g. to check the HAT is still present. The
+    /// returned record doesn't hold the I2C device.
+    pub fn probe(&mut self) -> Result<EEPType<T>, LinuxI2CError> {
+        let i2c_dev = self.i2cdev.as_mut().ok_or_else(|| LinuxI2CError::Io(
+            std::io::Error::other("I2C device has been released")))?;
+        let data = read_record(i2c_dev)?;
+        Ok(EEPType::decode(&data, None))
+    }
+
+    // Record layout: width and height (u16 little endian), colour, PCB variant,
+    // display variant, then the time it was written as a length-prefixed string.
+    fn decode(data: &[u8; EEP_LEN], i2c_dev: Option<T>) -> EEPType<T> {
+        let time_len = usize::from(data[7]).min(EEP_LEN - 8);
+        EEPType {
+            width: u16::from_le_bytes([data[0], data[1]]),
+            height: u16::from_le_bytes([data[2], data[3]]),
             colour: data[4],
             pcb_variant: data[5],
             display_variant: data[6],
+            write_time: String::from_utf8_lossy(&data[8..8 + time_len]).into_owned(),
             i2cdev: i2c_dev
-        };
-        Ok(eep_type)
+        }
+    }
+
+    /// Sanity check the record. The format has no checksum, so this looks for
+    /// values no genuine Inky EEPROM contains (e.g. from a blank or corrupt chip).
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.width == 0 || self.height == 0 || self.width == 0xffff || self.height == 0xffff {
+            problems.push(format!("implausible resolution {}x{}", self.width, self.height));
+        }
+        if self.colour_name() == "unknown" {
+            problems.push(format!("unknown colour {}", self.colour));
+        }
+        if self.display_name() == "unknown" {
+            problems.push(format!("unknown display variant {}", self.display_variant));
+        }
+        if self.write_time.is_empty() || !self.write_time.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+            problems.push("missing or corrupt write time".to_string());
+        }
+        problems
+    }
+
+    /// Hand back the I2C device, e.g. so it can be reused for other peripherals.
+    pub fn take_device(&mut self) -> Option<T> {
+        self.i2cdev.take()
     }
 
     pub fn colour_name(&self) -> &str {
-        VALID_COLOURS[usize::from(self.colour)]
+        VALID_COLOURS.get(usize::from(self.colour)).unwrap_or(&"unknown")
     }
     
     fn display_name(&self) -> &str {
-        match DISPLAY_VARIANT[usize::from(self.display_variant)] {
-            Some(n) => n,
-            None => "unknown"
+        match DISPLAY_VARIANT.get(usize::from(self.display_variant)) {
+            Some(Some(n)) => n,
+            _ => "unknown"
         }
     }
 }
 
+fn read_record<T>(i2c_dev: &mut T) -> Result<[u8; EEP_LEN], LinuxI2CError>
+where
+    T: I2CDevice, LinuxI2CError: std::convert::From<<T as I2CDevice>::Error>
+{
+    i2c_dev.smbus_write_i2c_block_data(0x00, &[0x00])?;
+    let mut data = [0u8; EEP_LEN];
+    let read = i2c_dev.smbus_read_i2c_block_data(0, EEP_LEN as u8)?;
+    data[..read.len().min(EEP_LEN)].copy_from_slice(&read[..read.len().min(EEP_LEN)]);
+    Ok(data)
+}
+
 // const EEP_ADDRESS: u16 = 0x50;
+const EEP_LEN: usize = 29;
 const VALID_COLOURS: [&str; 6] = [
     "unknown",
     "black",
@@ -99,4 +151,28 @@ mod tests {
             Err(e) => panic!("EEPType errored! ({})", e)
         }
     }
+
+    #[test]
+    fn eeptype_decode() {
+        let mut data = [0u8; EEP_LEN];
+        data[..7].copy_from_slice(&[250, 0, 122, 0, 1, 12, 10]);
+        let time = b"2021-06-01 12:00:00.0";
+        data[7] = time.len() as u8;
+        data[8..8 + time.len()].copy_from_slice(time);
+        let eep_type = EEPType::decode(&data, Some(MockI2CDevice::new()));
+        assert_eq!((eep_type.width, eep_type.height), (250, 122));
+        assert_eq!(eep_type.colour_name(), "black");
+        assert_eq!(eep_type.write_time, "2021-06-01 12:00:00.0");
+        assert!(eep_type.problems().is_empty());
+
+        // Sizes are little endian u16s; adding the high byte shifted by one
+        // bit read 640x400 as 132x146.
+        data[..4].copy_from_slice(&[0x80, 0x02, 0x90, 0x01]);
+        let wide = EEPType::decode(&data, Some(MockI2CDevice::new()));
+        assert_eq!((wide.width, wide.height), (640, 400));
+
+        let blank = EEPType::<MockI2CDevice>::decode(&[0xff; EEP_LEN], None);
+        assert_eq!(blank.colour_name(), "unknown");
+        assert_eq!(blank.problems().len(), 4);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,89 @@
+// Process-wide reference counts for the GPIOs we export, so that displays
+// sharing a pin (typically DC) don't unexport it from under each other, and
+// locks for DC pins so that displays sharing one don't interleave transfers.
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use linux_embedded_hal::Pin;
+use linux_embedded_hal::sysfs_gpio::Error;
+
+static USERS: Mutex<BTreeMap<u64, usize>> = Mutex::new(BTreeMap::new());
+static DC_LOCKS: Mutex<BTreeMap<u64, Arc<Mutex<()>>>> = Mutex::new(BTreeMap::new());
+
+/// Export `pin` unless another display already has. Returns whether this was
+/// the first user, i.e. whether the pin still needs configuring.
+pub(crate) fn claim(pin: &Pin) -> Result<bool, Error> {
+    let mut users = lock()?;
+    let first = add_user(&mut users, pin.get_pin_num());
+    if first {
+        pin.export()?;
+        while !pin.is_exported() {}
+    }
+    Ok(first)
+}
+
+/// Unexport `pin` once its last user has released it.
+pub(crate) fn release(pin: &Pin) -> Result<(), Error> {
+    let mut users = lock()?;
+    if remove_user(&mut users, pin.get_pin_num()) {
+        pin.unexport()?;
+    }
+    Ok(())
+}
+
+/// The lock for a DC pin. Displays on separate SPI devices can share DC, so
+/// it must be held from setting DC until the transfer that follows is done.
+pub(crate) fn dc_lock(pin: &Pin) -> Result<Arc<Mutex<()>>, Error> {
+    let mut locks = DC_LOCKS.lock().map_err(|_| Error::Unexpected("DC lock registry poisoned".to_string()))?;
+    Ok(Arc::clone(locks.entry(pin.get_pin_num()).or_default()))
+}
+
+fn lock() -> Result<std::sync::MutexGuard<'static, BTreeMap<u64, usize>>, Error> {
+    USERS.lock().map_err(|_| Error::Unexpected("GPIO registry lock poisoned".to_string()))
+}
+
+fn add_user(users: &mut BTreeMap<u64, usize>, pin: u64) -> bool {
+    let count = users.entry(pin).or_insert(0);
+    *count += 1;
+    *count == 1
+}
+
+fn remove_user(users: &mut BTreeMap<u64, usize>, pin: u64) -> bool {
+    match users.get_mut(&pin) {
+        Some(count) if *count > 1 => {
+            *count -= 1;
+            false
+        }
+        Some(_) => {
+            users.remove(&pin);
+            true
+        }
+        None => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_pin_counts() {
+        let mut users = BTreeMap::new();
+        assert!(add_user(&mut users, 22));
+        assert!(!add_user(&mut users, 22));
+        assert!(add_user(&mut users, 17));
+        assert!(!remove_user(&mut users, 22));
+        assert!(remove_user(&mut users, 22));
+        assert!(!remove_user(&mut users, 22));
+        assert!(remove_user(&mut users, 17));
+        assert!(users.is_empty());
+    }
+
+    #[test]
+    fn dc_locks_per_pin() {
+        let a = dc_lock(&Pin::new(22)).expect("lock");
+        let b = dc_lock(&Pin::new(22)).expect("lock");
+        let c = dc_lock(&Pin::new(23)).expect("lock");
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &c));
+    }
+}
@@ -0,0 +1,82 @@
+// Several displays refreshed together.
+use super::*;
+
+/// A set of displays on different chip selects that are refreshed in
+/// parallel. Each display needs its own SPI device (`/dev/spidevB.N` for its
+/// chip select N) and its own RESET and BUSY pins: resetting one controller
+/// must not reset another mid-refresh, and BUSY is how each refresh is
+/// tracked. The DC pin may be shared.
+pub struct DisplayGroup {
+    displays: Vec<Inky1608>,
+}
+
+impl DisplayGroup {
+    pub fn new(displays: Vec<Inky1608>) -> Result<DisplayGroup, Error> {
+        let pins: Vec<(u64, u64)> = displays.iter()
+            .map(|d| (d.reset_pin.get_pin_num(), d.busy_pin.get_pin_num()))
+            .collect();
+        check_pins(&pins)?;
+        for (i, display) in displays.iter().enumerate() {
+            if displays[i + 1..].iter().any(|other| Arc::ptr_eq(&display.spidev, &other.spidev)) {
+                return Err(Error::new(ErrorKind::InvalidInput, "an SPI device is shared between displays"));
+            }
+        }
+        Ok(DisplayGroup { displays })
+    }
+
+    pub fn displays(&mut self) -> &mut [Inky1608] {
+        &mut self.displays
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Inky1608> {
+        self.displays.get_mut(index)
+    }
+
+    /// Start a full refresh on every display, then wait for them all to finish.
+    /// The panels refresh simultaneously, so this takes about as long as one.
+    pub fn flush(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+        for display in self.displays.iter_mut() {
+            display.flush()?;
+        }
+        self.wait_refresh()
+    }
+
+    /// Wait until no display in the group is refreshing.
+    pub fn wait_refresh(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+        for display in self.displays.iter_mut() {
+            display.wait_refresh()?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> Vec<Inky1608> {
+        self.displays
+    }
+}
+
+// Each display needs its own RESET and BUSY pin.
+fn check_pins(pins: &[(u64, u64)]) -> Result<(), Error> {
+    for (i, (reset, busy)) in pins.iter().enumerate() {
+        for (other_reset, other_busy) in &pins[i + 1..] {
+            if reset == other_reset {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("RESET pin {} is shared between displays", reset)));
+            }
+            if busy == other_busy {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("BUSY pin {} is shared between displays", busy)));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_pins() {
+        assert!(check_pins(&[(27, 17), (5, 6)]).is_ok());
+        assert!(check_pins(&[(27, 17), (5, 6), (27, 13)]).is_err());
+        assert!(check_pins(&[(27, 17), (5, 17)]).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+// A small HTTP API for updating the display from scripts, e.g.
+//
+//     curl --data-binary @photo.png 'http://127.0.0.1:8080/image?dither=atkinson'
+//     curl --data 'Back at 5' http://127.0.0.1:8080/text
+//
+// Routes:
+//
+//     POST /image      PNG body, drawn over the whole display. Optional query
+//                      parameters "scale" (fit, fill, stretch) and "dither".
+//     POST /text       UTF-8 body, centred in the largest font that fits.
+//     POST /clear      Clear the display to white.
+//     GET  /info       The EEPROM contents and display settings, as text.
+//     GET  /frame.png  The framebuffer as a PNG.
+//
+// Requests are handled one at a time, and the POSTs return once the refresh
+// has finished. The server only listens on the loopback interface, and so that
+// web pages open in a browser on the Pi can't use it either, requests with an
+// Origin header or a Host other than localhost or 127.0.0.1 are refused.
+use super::*;
+use crate::dither::Dither;
+use crate::images::ScaleMode;
+use crate::layout::{HAlign, TextBox, VAlign};
+use embedded_graphics::mono_font::ascii::{FONT_10X20, FONT_6X10, FONT_9X15};
+use image::ImageFormat;
+use std::io::{Cursor, Read};
+use std::net::{Ipv4Addr, SocketAddr};
+
+/// Port the server listens on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 8080;
+
+// Larger request bodies are refused.
+const MAX_BODY: u64 = 8 * 1024 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Route {
+    Image,
+    Text,
+    Clear,
+    Info,
+    Frame,
+}
+
+// A response, before it's handed to tiny_http.
+#[derive(Debug)]
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn text(status: u16, body: impl Into<String>) -> Reply {
+        Reply { status, content_type: "text/plain; charset=utf-8", body: body.into().into_bytes() }
+    }
+
+    fn ok() -> Reply {
+        Reply::text(200, "ok\n")
+    }
+}
+
+/// The HTTP server: owns the display and serves requests on 127.0.0.1.
+pub struct Server {
+    server: tiny_http::Server,
+    inky: Inky1608,
+}
+
+impl Server {
+    /// Listen on `port` on the loopback interface.
+    pub fn bind(port: u16, inky: Inky1608) -> Result<Server, Error> {
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let server = tiny_http::Server::http(address).map_err(Error::other)?;
+        Ok(Server { server, inky })
+    }
+
+    /// Serve requests until the listener fails.
+    pub fn run(mut self) -> Result<(), Error> {
+        loop {
+            let mut request = self.server.recv()?;
+            let header = |name: &'static str| request.headers().iter()
+                .find(|h| h.field.equiv(name))
+                .map(|h| h.value.as_str().to_string());
+            let reply = match check_local(header("Origin").as_deref(), header("Host").as_deref())
+                .and_then(|()| route(request.method(), request.url())) {
+                Ok((route, query)) => match read_body(&mut request) {
+                    Ok(body) => handle(&mut self.inky, route, &query, &body),
+                    Err(reply) => reply,
+                },
+                Err(reply) => reply,
+            };
+            let content_type = tiny_http::Header::from_bytes("Content-Type", reply.content_type)
+                .expect("valid header");
+            let response = tiny_http::Response::from_data(reply.body)
+                .with_status_code(reply.status)
+                .with_header(content_type);
+            if let Err(e) = request.respond(response) {
+                eprintln!("inky-http: {}", e);
+            }
+        }
+    }
+}
+
+// Refuse requests made by web pages (which carry an Origin header) and, to
+// stop DNS rebinding, any whose Host isn't the loopback interface.
+fn check_local(origin: Option<&str>, host: Option<&str>) -> Result<(), Reply> {
+    if origin.is_some() {
+        return Err(Reply::text(403, "requests from web pages are not allowed\n"));
+    }
+    let host = host.unwrap_or_default();
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    };
+    if !name.eq_ignore_ascii_case("localhost") && name != "127.0.0.1" {
+        return Err(Reply::text(403, format!("host \"{}\" is not allowed\n", host)));
+    }
+    Ok(())
+}
+
+// Match a request to a route, splitting off the query string.
+fn route(method: &tiny_http::Method, url: &str) -> Result<(Route, Vec<(String, String)>), Reply> {
+    use tiny_http::Method::{Get, Post};
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let route = match path {
+        "/image" => (Post, Route::Image),
+        "/text" => (Post, Route::Text),
+        "/clear" => (Post, Route::Clear),
+        "/info" => (Get, Route::Info),
+        "/frame.png" => (Get, Route::Frame),
+        _ => return Err(Reply::text(404, format!("no such resource {}\n", path))),
+    };
+    if *method != route.0 {
+        return Err(Reply::text(405, format!("{} needs {}\n", path, route.0)));
+    }
+    let query = query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (key.to_string(), value.to_string())
+        })
+        .collect();
+    Ok((route.1, query))
+}
+
+fn read_body(request: &mut tiny_http::Request) -> Result<Vec<u8>, Reply> {
+    let mut body = vec![];
+    request.as_reader().take(MAX_BODY + 1).read_to_end(&mut body)
+        .map_err(|e| Reply::text(400, format!("reading body: {}\n", e)))?;
+    if body.len() as u64 > MAX_BODY {
+        return Err(Reply::text(413, format!("body is larger than {} bytes\n", MAX_BODY)));
+    }
+    Ok(body)
+}
+
+// Carry out a request on the display.
+fn handle(inky: &mut Inky1608, route: Route, query: &[(String, String)], body: &[u8]) -> Reply {
+    let result = match route {
+        Route::Image => image_options(query).and_then(|(scale, dither)| {
+            let image = images::decode(body, Some(ImageFormat::Png), inky.size())?;
+            inky.draw_image_dithered(&image, scale, dither);
+            refresh(inky)
+        }),
+        Route::Text => std::str::from_utf8(body)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("text is not UTF-8: {}", e)))
+            .and_then(|text| {
+                let _ = inky.clear(BinaryColor::Off);
+                let bounds = Rectangle::new(Point::zero(), inky.size());
+                let _ = TextBox::with_fonts(text.trim_end(), bounds, &[&FONT_10X20, &FONT_9X15, &FONT_6X10])
+                    .align(HAlign::Centre, VAlign::Middle)
+                    .draw(inky);
+                refresh(inky)
+            }),
+        Route::Clear => {
+            let _ = inky.clear(BinaryColor::Off);
+            refresh(inky)
+        }
+        Route::Info => {
+            let size = inky.size();
+            return Reply::text(200, format!("{}\ndisplay {}x{}, h_flip {}, v_flip {}, vcom {}\n",
+                inky, size.width, size.height, inky.h_flip, inky.v_flip, inky.vcom()));
+        }
+        Route::Frame => {
+            let mut png = vec![];
+            return match inky.snapshot().write_to(&mut Cursor::new(&mut png), ImageFormat::Png) {
+                Ok(()) => Reply { status: 200, content_type: "image/png", body: png },
+                Err(e) => Reply::text(500, format!("{}\n", e)),
+            };
+        }
+    };
+    match result {
+        Ok(()) => Reply::ok(),
+        Err(e) if e.kind() == ErrorKind::InvalidInput => Reply::text(400, format!("{}\n", e)),
+        Err(e) => Reply::text(500, format!("{}\n", e)),
+    }
+}
+
+// The scale mode and dithering asked for in the query string.
+fn image_options(query: &[(String, String)]) -> Result<(ScaleMode, Dither), Error> {
+    let mut options = (ScaleMode::default(), Dither::None);
+    for (key, value) in query {
+        match key.as_str() {
+            "scale" => options.0 = value.parse()?,
+            "dither" => options.1 = value.parse()?,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, format!("unknown parameter \"{}\"", key))),
+        }
+    }
+    Ok(options)
+}
+
+// Refresh the panel and wait for it to finish, so the reply means it's done.
+fn refresh(inky: &mut Inky1608) -> Result<(), Error> {
+    inky.flush()
+        .and_then(|()| inky.wait_refresh())
+        .map_err(|e| Error::other(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_http::Method;
+
+    #[test]
+    fn routing() {
+        assert_eq!(route(&Method::Post, "/image").expect("image").0, Route::Image);
+        assert_eq!(route(&Method::Get, "/frame.png").expect("frame").0, Route::Frame);
+        assert_eq!(route(&Method::Get, "/image").expect_err("GET image").status, 405);
+        assert_eq!(route(&Method::Post, "/nothing").expect_err("unknown").status, 404);
+        let (_, query) = route(&Method::Post, "/image?scale=fill&dither=").expect("query");
+        assert_eq!(query, vec![("scale".to_string(), "fill".to_string()), ("dither".to_string(), String::new())]);
+    }
+
+    #[test]
+    fn local_only() {
+        assert!(check_local(None, Some("127.0.0.1:8080")).is_ok());
+        assert!(check_local(None, Some("localhost")).is_ok());
+        assert!(check_local(None, Some("LocalHost:80")).is_ok());
+        assert_eq!(check_local(Some("http://example.com"), Some("127.0.0.1:8080")).expect_err("origin").status, 403);
+        assert!(check_local(None, Some("evil.example.com:8080")).is_err());
+        assert!(check_local(None, Some("localhost.evil.example.com")).is_err());
+        assert!(check_local(None, None).is_err());
+    }
+
+    #[test]
+    fn image_query() {
+        let query = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        assert_eq!(image_options(&[]).expect("defaults"), (ScaleMode::Fit, Dither::None));
+        let options = image_options(&query(&[("scale", "stretch"), ("dither", "atkinson")])).expect("options");
+        assert_eq!(options, (ScaleMode::Stretch, Dither::Atkinson));
+        assert_eq!(image_options(&query(&[("scale", "zoom")])).expect_err("bad scale").kind(), ErrorKind::InvalidInput);
+        assert!(image_options(&query(&[("colour", "red")])).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+// Loading images and converting them to the colours a panel can show.
+use super::*;
+use crate::dither::{self, Dither};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageFormat, ImageReader, Limits, Rgb, RgbImage, Rgba, RgbaImage};
+use std::io::Cursor;
+use std::path::Path;
+
+/// How an image is scaled to the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScaleMode {
+    /// Scale to fit inside the display keeping the aspect ratio, with white margins.
+    #[default]
+    Fit,
+    /// Scale to cover the whole display keeping the aspect ratio, cropping the
+    /// overflow equally from both sides.
+    Fill,
+    /// Scale to exactly the display size, distorting if the aspect ratios differ.
+    Stretch,
+}
+
+impl std::str::FromStr for ScaleMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fit" => Ok(ScaleMode::Fit),
+            "fill" => Ok(ScaleMode::Fill),
+            "stretch" => Ok(ScaleMode::Stretch),
+            _ => Err(Error::new(ErrorKind::InvalidInput, format!("unknown scale mode '{}'", s)))
+        }
+    }
+}
+
+/// An image converted to a panel's colours, one `Colour` per pixel, or one
+/// of four grey levels for a `Colour::Gray2` panel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanelImage {
+    width: u32,
+    height: u32,
+    pixels: Pixels,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pixels {
+    Colours(Vec<Colour>),
+    Greys(Vec<Gray2>),
+}
+
+impl PanelImage {
+    /// Scale `image` to `size` and map each pixel to the nearest colour the
+    /// `panel` can show: white, black and, on red/yellow panels, the accent,
+    /// or on Gray2 panels the nearest grey. Transparent areas are treated as
+    /// white.
+    pub fn new(image: &DynamicImage, size: Size, mode: ScaleMode, panel: Colour) -> PanelImage {
+        PanelImage::dithered(image, size, mode, panel, Dither::None)
+    }
+
+    /// As `new()`, but reducing the colours with the given dithering method.
+    pub fn dithered(image: &DynamicImage, size: Size, mode: ScaleMode, panel: Colour, method: Dither) -> PanelImage {
+        let scaled = scale(image, size, mode);
+        let rgb: Vec<[u8; 3]> = scaled.pixels().map(flatten).collect();
+        let width = scaled.width() as usize;
+        let pixels = match panel {
+            Colour::Gray2 => Pixels::Greys(dither::dither_grey(&rgb, width, method)),
+            _ => Pixels::Colours(dither::dither(&rgb, width, method, panel)),
+        };
+        PanelImage { width: scaled.width(), height: scaled.height(), pixels }
+    }
+
+    /// Image size in pixels.
+    pub fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+
+    /// The colour at x, y. Grey levels are rounded to black or white.
+    pub fn pixel(&self, x: u32, y: u32) -> Colour {
+        let i = (y * self.width + x) as usize;
+        match &self.pixels {
+            Pixels::Colours(colours) => colours[i],
+            Pixels::Greys(greys) if greys[i].luma() < 2 => Colour::Black,
+            Pixels::Greys(_) => Colour::White,
+        }
+    }
+
+    /// The grey level at x, y, if the image was made for a Gray2 panel.
+    pub fn grey(&self, x: u32, y: u32) -> Option<Gray2> {
+        match &self.pixels {
+            Pixels::Colours(_) => None,
+            Pixels::Greys(greys) => Some(greys[(y * self.width + x) as usize]),
+        }
+    }
+
+    /// Draw the image into the display's framebuffer, accent pixels and grey
+    /// levels included, with its top left corner at `origin`.
+    pub fn draw(&self, inky: &mut Inky1608, origin: Point) {
+        // Infallible.
+        match &self.pixels {
+            Pixels::Colours(colours) => {
+                let pixels = self.pixels_at(colours, origin);
+                let _ = inky.draw_iter(pixels.clone().map(|(point, colour)| Pixel(point, BinaryColor::from(colour == Colour::Black))));
+                let _ = inky.accent_layer().draw_iter(pixels
+                    .filter(|(_, colour)| !matches!(colour, Colour::White | Colour::Black))
+                    .map(|(point, _)| Pixel(point, BinaryColor::On)));
+            }
+            Pixels::Greys(greys) => {
+                let _ = inky.grey_layer().draw_iter(self.pixels_at(greys, origin).map(|(point, grey)| Pixel(point, grey)));
+            }
+        }
+    }
+
+    fn pixels_at<'a, T: Copy>(&'a self, pixels: &'a [T], origin: Point) -> impl Iterator<Item = (Point, T)> + Clone + 'a {
+        pixels.iter().enumerate().map(move |(i, &pixel)| {
+            let (x, y) = (i as u32 % self.width, i as u32 / self.width);
+            (origin + Point::new(x as i32, y as i32), pixel)
+        })
+    }
+}
+
+impl Inky1608 {
+    /// Scale and convert an image for this panel and draw it over the whole display.
+    #[allow(dead_code)]
+    pub fn draw_image(&mut self, image: &DynamicImage, mode: ScaleMode) {
+        PanelImage::new(image, self.size(), mode, self.colour).draw(self, Point::zero());
+    }
+
+    /// As `draw_image()`, dithering photos and gradients rather than thresholding them.
+    #[allow(dead_code)]
+    pub fn draw_image_dithered(&mut self, image: &DynamicImage, mode: ScaleMode, method: Dither) {
+        PanelImage::dithered(image, self.size(), mode, self.colour, method).draw(self, Point::zero());
+    }
+
+    /// The framebuffer as an image, in display coordinates and the colours
+    /// the panel would show it in.
+    #[allow(dead_code)]
+    pub fn snapshot(&self) -> RgbImage {
+        let size = self.size();
+        RgbImage::from_fn(size.width, size.height, |x, y| {
+            let offset = self.framebuffer_index(x as u16, y as u16);
+            Rgb(shown_rgb(self.colour, self.framebuffer[offset], self.accent[offset]))
+        })
+    }
+}
+
+/// Load a PNG, JPEG, BMP or PBM/PGM/PPM file.
+pub fn open<P: AsRef<Path>>(path: P) -> Result<DynamicImage, Error> {
+    image::open(path).map_err(|e| match e {
+        image::ImageError::IoError(e) => e,
+        e => Error::new(ErrorKind::InvalidData, e)
+    })
+}
+
+// How many times larger than the display a decoded image may be, per side.
+const MAX_SCALE: u32 = 16;
+
+/// Decode an image sent over the network for a display of `size`, refusing
+/// any too big to scale down on a small Pi (more than 16 times the display's
+/// longest side). The format is guessed unless given.
+pub fn decode(bytes: &[u8], format: Option<ImageFormat>, size: Size) -> Result<DynamicImage, Error> {
+    let side = size.width.max(size.height).saturating_mul(MAX_SCALE);
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(side);
+    limits.max_image_height = Some(side);
+    limits.max_alloc = Some(u64::from(side) * u64::from(side) * 4);
+    let mut reader = match format {
+        Some(format) => ImageReader::with_format(Cursor::new(bytes), format),
+        None => ImageReader::new(Cursor::new(bytes)).with_guessed_format()?,
+    };
+    reader.limits(limits);
+    reader.decode().map_err(|e| Error::new(ErrorKind::InvalidInput, format!("bad image: {}", e)))
+}
+
+// Scale an image to exactly `size`, which for Fit includes white margins.
+fn scale(image: &DynamicImage, size: Size, mode: ScaleMode) -> RgbaImage {
+    let (width, height) = (size.width, size.height);
+    match mode {
+        ScaleMode::Stretch => image.resize_exact(width, height, FilterType::Triangle).into_rgba8(),
+        ScaleMode::Fill => image.resize_to_fill(width, height, FilterType::Triangle).into_rgba8(),
+        ScaleMode::Fit => {
+            let fitted = image.resize(width, height, FilterType::Triangle).into_rgba8();
+            let mut canvas = RgbaImage::from_pixel(width, height, Rgba([0xff, 0xff, 0xff, 0xff]));
+            let x = (width - fitted.width()) / 2;
+            let y = (height - fitted.height()) / 2;
+            imageops::overlay(&mut canvas, &fitted, x.into(), y.into());
+            canvas
+        }
+    }
+}
+
+// The colour a panel shows for a pixel's black and accent bits.
+fn shown_rgb(panel: Colour, black: bool, accent: bool) -> [u8; 3] {
+    match (panel, black, accent) {
+        (Colour::Gray2, true, true) => [0x55; 3],
+        (Colour::Gray2, false, true) => [0xaa; 3],
+        (Colour::Red | Colour::RedHt, _, true) => [0xff, 0x00, 0x00],
+        (Colour::Yellow, _, true) => [0xff, 0xff, 0x00],
+        (_, true, _) | (Colour::Black, _, true) => [0x00; 3],
+        _ => [0xff; 3],
+    }
+}
+
+// Composite a pixel over white.
+fn flatten(pixel: &Rgba<u8>) -> [u8; 3] {
+    let [r, g, b, a] = pixel.0;
+    let over_white = |c: u8| ((c as u16 * a as u16 + 0xff * (0xff - a as u16)) / 0xff) as u8;
+    [over_white(r), over_white(g), over_white(b)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(width: u32, height: u32, colour: [u8; 3]) -> DynamicImage {
+        DynamicImage::ImageRgb8(image::RgbImage::from_pixel(width, height, image::Rgb(colour)))
+    }
+
+    #[test]
+    fn palette_mapping() {
+        let red = rgb(4, 4, [0xe0, 0x10, 0x20]);
+        let size = Size::new(4, 4);
+        assert_eq!(PanelImage::new(&red, size, ScaleMode::Stretch, Colour::Red).pixel(0, 0), Colour::Red);
+        assert_eq!(PanelImage::new(&red, size, ScaleMode::Stretch, Colour::RedHt).pixel(0, 0), Colour::Red);
+        assert_eq!(PanelImage::new(&red, size, ScaleMode::Stretch, Colour::Black).pixel(0, 0), Colour::Black);
+        let yellow = rgb(4, 4, [0xf0, 0xe0, 0x10]);
+        assert_eq!(PanelImage::new(&yellow, size, ScaleMode::Stretch, Colour::Yellow).pixel(0, 0), Colour::Yellow);
+        assert_eq!(PanelImage::new(&yellow, size, ScaleMode::Stretch, Colour::Black).pixel(0, 0), Colour::White);
+    }
+
+    #[test]
+    fn grey_mapping() {
+        let size = Size::new(4, 4);
+        let dark = PanelImage::new(&rgb(4, 4, [0x50; 3]), size, ScaleMode::Stretch, Colour::Gray2);
+        assert_eq!(dark.grey(0, 0), Some(Gray2::new(1)));
+        assert_eq!(dark.pixel(0, 0), Colour::Black);
+        let light = PanelImage::new(&rgb(4, 4, [0xb0; 3]), size, ScaleMode::Stretch, Colour::Gray2);
+        assert_eq!(light.grey(3, 3), Some(Gray2::new(2)));
+        assert_eq!(light.pixel(3, 3), Colour::White);
+        assert_eq!(PanelImage::new(&rgb(4, 4, [0x50; 3]), size, ScaleMode::Stretch, Colour::Black).grey(0, 0), None);
+    }
+
+    #[test]
+    fn transparent_is_white() {
+        let clear = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 2, Rgba([0, 0, 0, 0])));
+        let image = PanelImage::new(&clear, Size::new(2, 2), ScaleMode::Stretch, Colour::Black);
+        assert_eq!(image.pixel(1, 1), Colour::White);
+    }
+
+    #[test]
+    fn scale_modes() {
+        // A wide black image on a square display.
+        let wide = rgb(20, 10, [0, 0, 0]);
+        let size = Size::new(10, 10);
+
+        let fit = PanelImage::new(&wide, size, ScaleMode::Fit, Colour::Black);
+        assert_eq!(fit.size(), size);
+        assert_eq!(fit.pixel(5, 0), Colour::White);
+        assert_eq!(fit.pixel(5, 5), Colour::Black);
+        assert_eq!(fit.pixel(5, 9), Colour::White);
+
+        for mode in [ScaleMode::Fill, ScaleMode::Stretch] {
+            let image = PanelImage::new(&wide, size, mode, Colour::Black);
+            assert_eq!(image.size(), size);
+            assert!((0..10).all(|y| (0..10).all(|x| image.pixel(x, y) == Colour::Black)));
+        }
+    }
+
+    #[test]
+    fn decode_limits() {
+        let encode = |width, height| {
+            let mut png = vec![];
+            image::RgbImage::new(width, height).write_to(&mut Cursor::new(&mut png), ImageFormat::Png).expect("encode");
+            png
+        };
+        let size = Size::new(25, 12);
+        let image = decode(&encode(400, 10), Some(ImageFormat::Png), size).expect("decode");
+        assert_eq!((image.width(), image.height()), (400, 10));
+        let error = decode(&encode(401, 10), None, size).expect_err("too wide");
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(decode(&encode(4, 4), Some(ImageFormat::Jpeg), size).is_err());
+    }
+
+    #[test]
+    fn shown_colours() {
+        assert_eq!(shown_rgb(Colour::Black, false, false), [0xff; 3]);
+        assert_eq!(shown_rgb(Colour::Black, false, true), [0x00; 3]);
+        assert_eq!(shown_rgb(Colour::Red, true, true), [0xff, 0x00, 0x00]);
+        assert_eq!(shown_rgb(Colour::Yellow, true, false), [0x00; 3]);
+        assert_eq!(shown_rgb(Colour::Gray2, true, false), [0x00; 3]);
+        assert_eq!(shown_rgb(Colour::Gray2, false, true), [0xaa; 3]);
+    }
+
+    #[test]
+    fn scale_mode_from_str() {
+        assert_eq!("Fill".parse::<ScaleMode>().unwrap(), ScaleMode::Fill);
+        assert!("zoom".parse::<ScaleMode>().is_err());
+    }
+}
@@ -0,0 +1,253 @@
+// Laying out text in a box: word wrap, alignment, ellipsis and choosing a font that fits.
+use embedded_graphics::{
+    mono_font::{MonoFont, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+    text::{Baseline, Text},
+};
+
+const ELLIPSIS: &str = "...";
+
+/// Horizontal alignment of each line within the box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HAlign {
+    #[default]
+    Left,
+    Centre,
+    Right,
+}
+
+/// Vertical alignment of the block of lines within the box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VAlign {
+    #[default]
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// Text wrapped to fit a rectangle. Lines break at spaces where possible and
+/// at "\n"; text that still doesn't fit is cut short with "...".
+///
+/// Given several fonts, the largest one the text fits in without breaking a
+/// word or being cut short is used, or failing that the smallest.
+pub struct TextBox<'a> {
+    text: &'a str,
+    bounds: Rectangle,
+    fonts: Vec<&'a MonoFont<'a>>,
+    h_align: HAlign,
+    v_align: VAlign,
+    colour: BinaryColor,
+}
+
+/// The result of laying out a `TextBox`.
+#[derive(Debug, Clone)]
+pub struct Lines<'a> {
+    /// The font chosen.
+    pub font: &'a MonoFont<'a>,
+    /// The text of each line, after wrapping and truncation.
+    pub lines: Vec<String>,
+    /// Whether the text had to be cut short.
+    pub truncated: bool,
+}
+
+impl<'a> TextBox<'a> {
+    /// Text in a single font, top left aligned, drawn in `BinaryColor::On`.
+    pub fn new(text: &'a str, bounds: Rectangle, font: &'a MonoFont<'a>) -> TextBox<'a> {
+        TextBox::with_fonts(text, bounds, &[font])
+    }
+
+    /// Text in the largest of `fonts` that fits.
+    pub fn with_fonts(text: &'a str, bounds: Rectangle, fonts: &[&'a MonoFont<'a>]) -> TextBox<'a> {
+        let mut fonts = fonts.to_vec();
+        fonts.sort_by_key(|font| std::cmp::Reverse(font.character_size.height));
+        TextBox { text, bounds, fonts, h_align: HAlign::Left, v_align: VAlign::Top, colour: BinaryColor::On }
+    }
+
+    /// Set the horizontal and vertical alignment.
+    pub fn align(mut self, h_align: HAlign, v_align: VAlign) -> TextBox<'a> {
+        self.h_align = h_align;
+        self.v_align = v_align;
+        self
+    }
+
+    /// Set the text colour, e.g. `BinaryColor::Off` for white on black.
+    pub fn colour(mut self, colour: BinaryColor) -> TextBox<'a> {
+        self.colour = colour;
+        self
+    }
+
+    /// Choose the font and wrap the text, without drawing it.
+    pub fn layout(&self) -> Option<Lines<'a>> {
+        let mut fallback = None;
+        for &font in &self.fonts {
+            let (columns, rows) = capacity(font, self.bounds.size);
+            let (lines, broken) = wrap(self.text, columns);
+            if !broken && lines.len() <= rows {
+                return Some(Lines { font, lines, truncated: false });
+            }
+            fallback = Some((font, lines, columns, rows));
+        }
+        fallback.map(|(font, lines, columns, rows)| {
+            let truncated = lines.len() > rows;
+            Lines { font, lines: ellipsise(lines, columns, rows), truncated }
+        })
+    }
+}
+
+impl Drawable for TextBox<'_> {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let Lines { font, lines, .. } = match self.layout() {
+            Some(layout) => layout,
+            None => return Ok(())
+        };
+        let style = MonoTextStyle::new(font, self.colour);
+        let line_height = font.character_size.height as i32;
+        let block_height = line_height * lines.len() as i32;
+        let spare_height = self.bounds.size.height as i32 - block_height;
+        let top = self.bounds.top_left.y + match self.v_align {
+            VAlign::Top => 0,
+            VAlign::Middle => spare_height / 2,
+            VAlign::Bottom => spare_height,
+        };
+        for (i, line) in lines.iter().enumerate() {
+            let spare_width = self.bounds.size.width as i32 - text_width(font, line) as i32;
+            let left = self.bounds.top_left.x + match self.h_align {
+                HAlign::Left => 0,
+                HAlign::Centre => spare_width / 2,
+                HAlign::Right => spare_width,
+            };
+            let position = Point::new(left, top + line_height * i as i32);
+            Text::with_baseline(line, position, style, Baseline::Top).draw(target)?;
+        }
+        Ok(())
+    }
+}
+
+// Top left of something of the given size centred in area.
+#[cfg(feature = "codes")]
+pub(crate) fn centred(area: Rectangle, size: Size) -> Point {
+    let spare = area.size.saturating_sub(size);
+    area.top_left + Point::new((spare.width / 2) as i32, (spare.height / 2) as i32)
+}
+
+/// Width in pixels of a line of text in a mono font.
+pub fn text_width(font: &MonoFont, text: &str) -> u32 {
+    let chars = text.chars().count() as u32;
+    (chars * (font.character_size.width + font.character_spacing)).saturating_sub(font.character_spacing)
+}
+
+// How many columns and rows of characters fit in size.
+fn capacity(font: &MonoFont, size: Size) -> (usize, usize) {
+    let advance = font.character_size.width + font.character_spacing;
+    let columns = (size.width + font.character_spacing) / advance.max(1);
+    let rows = size.height / font.character_size.height.max(1);
+    (columns as usize, rows as usize)
+}
+
+// Wrap text into lines of at most `columns` characters, breaking at spaces
+// and newlines. Also returns whether a word had to be broken mid-way.
+fn wrap(text: &str, columns: usize) -> (Vec<String>, bool) {
+    let mut lines = vec![];
+    let mut broken = false;
+    let columns = columns.max(1);
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            let used = line.chars().count();
+            if used > 0 && used + 1 + word.len() <= columns {
+                line.push(' ');
+                line.extend(&word);
+                continue;
+            }
+            if used > 0 {
+                lines.push(std::mem::take(&mut line));
+            }
+            while word.len() > columns {
+                broken = true;
+                lines.push(word.drain(..columns).collect());
+            }
+            line.extend(&word);
+        }
+        lines.push(line);
+    }
+    (lines, broken)
+}
+
+// Cut lines to at most `rows`, ending the last with an ellipsis if any text was lost.
+fn ellipsise(mut lines: Vec<String>, columns: usize, rows: usize) -> Vec<String> {
+    if lines.len() <= rows {
+        return lines;
+    }
+    lines.truncate(rows);
+    if let Some(last) = lines.last_mut() {
+        let keep = columns.saturating_sub(ELLIPSIS.len());
+        let mut shortened: String = last.chars().take(keep).collect::<String>().trim_end().to_string();
+        shortened.push_str(&ELLIPSIS[..columns.min(ELLIPSIS.len())]);
+        *last = shortened;
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::mock_display::MockDisplay;
+    use embedded_graphics::mono_font::ascii::{FONT_10X20, FONT_4X6, FONT_6X10};
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(wrap("the quick brown fox", 10), (vec!["the quick".to_string(), "brown fox".to_string()], false));
+        assert_eq!(wrap("one\n\ntwo", 10).0, vec!["one", "", "two"]);
+        assert_eq!(wrap("abcdefgh ij", 3), (vec!["abc".to_string(), "def".to_string(), "gh".to_string(), "ij".to_string()], true));
+    }
+
+    #[test]
+    fn ellipsis() {
+        let lines = vec!["the quick".to_string(), "brown fox".to_string(), "jumps".to_string()];
+        assert_eq!(ellipsise(lines.clone(), 10, 3), lines);
+        assert_eq!(ellipsise(lines.clone(), 10, 2), vec!["the quick", "brown f..."]);
+        assert_eq!(ellipsise(lines, 2, 1), vec![".."]);
+    }
+
+    #[test]
+    fn autofit() {
+        let fonts = [&FONT_4X6, &FONT_10X20, &FONT_6X10];
+        let bounds = Rectangle::new(Point::zero(), Size::new(60, 20));
+        let layout = TextBox::with_fonts("Hello", bounds, &fonts).layout().expect("layout");
+        assert_eq!(layout.font.character_size, FONT_10X20.character_size);
+        assert!(!layout.truncated);
+
+        let layout = TextBox::with_fonts("Hi there world", bounds, &fonts).layout().expect("layout");
+        assert_eq!(layout.font.character_size, FONT_6X10.character_size);
+        assert_eq!(layout.lines, vec!["Hi there", "world"]);
+
+        let long = "a long message that cannot possibly fit in this box";
+        let layout = TextBox::with_fonts(long, bounds, &fonts).layout().expect("layout");
+        assert_eq!(layout.font.character_size, FONT_4X6.character_size);
+        assert!(layout.truncated);
+        assert!(layout.lines.last().expect("line").ends_with("..."));
+    }
+
+    #[test]
+    fn alignment() {
+        let bounds = Rectangle::new(Point::new(2, 2), Size::new(40, 30));
+        let draw = |h_align, v_align| {
+            let mut display = MockDisplay::<BinaryColor>::new();
+            TextBox::new("TTT", bounds, &FONT_6X10).align(h_align, v_align).draw(&mut display).expect("draw");
+            display.affected_area()
+        };
+        // "TTT" is 18 x 10 pixels, leaving 22 x 20 to share out.
+        let top_left = draw(HAlign::Left, VAlign::Top);
+        assert_eq!(draw(HAlign::Centre, VAlign::Middle), top_left.translate(Point::new(11, 10)));
+        assert_eq!(draw(HAlign::Right, VAlign::Bottom), top_left.translate(Point::new(22, 20)));
+    }
+}
@@ -1,47 +1,88 @@
 use i2cdev::linux::LinuxI2CDevice;
-use linux_embedded_hal::spidev::{SpiModeFlags, SpidevOptions};
+use linux_embedded_hal::spidev::{spidevioctl, SpiModeFlags, SpidevOptions};
+use std::os::unix::io::AsRawFd;
+use std::io::Read;
 use linux_embedded_hal::sysfs_gpio::Direction;
 use linux_embedded_hal::{Pin, Spidev};
 use embedded_hal::blocking::spi::Write;
 use embedded_graphics::{
-    pixelcolor::BinaryColor,
-    prelude::*
+    pixelcolor::{BinaryColor, Gray2},
+    prelude::*,
+    primitives::Rectangle
 };
 use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "codes")]
+pub mod codes;
+mod colour;
+#[cfg(feature = "daemon")]
+pub mod daemon;
+mod diagnostics;
+pub mod dither;
 mod eeprom;
+mod gpio;
+mod group;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod layout;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+mod panel;
+mod readback;
+mod spi;
+mod vcom;
+pub use colour::Colour;
+pub use diagnostics::Diagnostics;
+pub use group::DisplayGroup;
+pub use panel::{DriveVoltages, PanelTiming};
+pub use readback::{DisplayRam, Otp};
+pub use spi::{SpiConfig, MAX_SPI_SPEED_HZ};
+pub use vcom::Vcom;
+#[cfg(feature = "async")]
+mod asynchronous;
+#[cfg(feature = "images")]
+pub mod images;
+#[cfg(feature = "scene")]
+pub mod scene;
+#[cfg(feature = "widgets")]
+pub mod widgets;
 use eeprom::EEPType;
 
+/// An SPI device, shared between the driver and anything it is handed to
+/// (e.g. the async API's blocking threads). One spidev handle drives one chip
+/// select, so each display needs its own; see `SpiConfig::open()`.
+pub type SharedSpi = Arc<Mutex<Spidev>>;
+
 const EEP_ADDRESS: u16 = 0x50;
-const SPI_CHUNK_SIZE: usize = 4096;
 const SPI_COMMAND: u8 = 0;
 const SPI_DATA: u8 = 1;
 const DRIVER_CONTROL: u8 = 0x01;
-const _GATE_VOLTAGE: u8 = 0x03;
-const _SOURCE_VOLTAGE: u8 = 0x04;
+const GATE_VOLTAGE: u8 = 0x03;
+const SOURCE_VOLTAGE: u8 = 0x04;
 const _DISPLAY_CONTROL: u8 = 0x07;
-const _NON_OVERLAP: u8 = 0x0B;
-const _BOOSTER_SOFT_START: u8 = 0x0C;
-const _GATE_SCAN_START: u8 = 0x0F;
-const _DEEP_SLEEP: u8 = 0x10;
+const NON_OVERLAP: u8 = 0x0B;
+const BOOSTER_SOFT_START: u8 = 0x0C;
+const GATE_SCAN_START: u8 = 0x0F;
+const DEEP_SLEEP: u8 = 0x10;
 const DATA_MODE: u8 = 0x11;
-const _SW_RESET: u8 = 0x12;
+const SW_RESET: u8 = 0x12;
 const _TEMP_WRITE: u8 = 0x1A;
-const _TEMP_READ: u8 = 0x1B;
+const TEMP_READ: u8 = 0x1B;
 const _TEMP_CONTROL: u8 = 0x1C;
 const _TEMP_LOAD: u8 = 0x1D;
 const MASTER_ACTIVATE: u8 = 0x20;
 const _DISP_CTRL1: u8 = 0x21;
-const _DISP_CTRL2: u8 = 0x22;
+const DISP_CTRL2: u8 = 0x22;
 const WRITE_RAM: u8 = 0x24;
 const WRITE_ALTRAM: u8 = 0x26;
-const _READ_RAM: u8 = 0x25;
-const _VCOM_SENSE: u8 = 0x28;
-const _VCOM_DURATION: u8 = 0x29;
+const READ_RAM: u8 = 0x25;
+const VCOM_SENSE: u8 = 0x28;
+const VCOM_DURATION: u8 = 0x29;
 const WRITE_VCOM: u8 = 0x2C;
-const _READ_OTP: u8 = 0x2D;
+const READ_OTP: u8 = 0x2D;
 const WRITE_LUT: u8 = 0x32;
 const WRITE_DUMMY: u8 = 0x3A;
 const WRITE_GATELINE: u8 = 0x3B;
@@ -52,15 +93,12 @@ const SET_RAMXCOUNT: u8 = 0x4E;
 const SET_RAMYCOUNT: u8 = 0x4F;
 const _NOP: u8 = 0xFF;
 
-#[allow(dead_code)]
-#[derive(PartialEq, Debug, Copy, Clone)]
-pub enum Colour {
-    White,
-    Black,
-    Red,
-    Yellow,
-    RedHt,
-}
+// How long dropping the display waits for a refresh to finish.
+const DROP_BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// BUSY can take a moment to assert after MASTER_ACTIVATE, so it isn't taken
+// to mean the refresh has finished until this long afterwards.
+const BUSY_SETTLE: Duration = Duration::from_millis(10);
 
 #[allow(dead_code)]
 pub struct Inky1608 {
@@ -74,8 +112,12 @@ pub struct Inky1608 {
     offset_x: u16,
     offset_y: u16,
     colour: Colour,
-    border_colour: Colour,
+    // None leaves the border floating (high impedance).
+    border_colour: Option<Colour>,
     lut: [u8; 30],
+    vcom: Vcom,
+    timing: PanelTiming,
+    voltages: DriveVoltages,
     cs_channel: u16,
     dc_pin: Pin,
     reset_pin: Pin,
@@ -83,27 +125,39 @@ pub struct Inky1608 {
     h_flip: bool,
     v_flip: bool,
     eeprom: eeprom::EEPType<LinuxI2CDevice>,
-    spidev: Spidev,
-    framebuffer: Vec<bool>
+    spidev: SharedSpi,
+    spi_chunk_size: usize,
+    framebuffer: Vec<bool>,
+    // Pixels drawn in the panel's accent colour (red/yellow), same layout as
+    // framebuffer. In Gray2 mode this is the grey bit instead, see grey_bits().
+    accent: Vec<bool>,
+    // The frame most recently transmitted to the panel, i.e. what it is showing now.
+    last_frame: Option<Vec<bool>>,
+    last_accent: Vec<bool>,
+    // When MASTER_ACTIVATE was sent, cleared when BUSY is seen to drop.
+    refreshing: Option<Instant>,
+    // Whether the controller has been reset and configured.
+    initialised: bool,
+    // Whether we have exported the GPIOs (and so must unexport them).
+    exported: bool
 }
 
 impl Inky1608 {
     #[allow(dead_code)]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         resolution: Option<(u16, u16)>,
-        colour: Option<&str>,
+        colour: Option<Colour>,
         cs_channel: u16,
         dc_pin: u64,
         reset_pin: u64,
         busy_pin: u64,
         h_flip: bool,
         v_flip: bool,
-        spidev: Option<Spidev>,
+        spidev: Option<SharedSpi>,
         i2c_bus: Option<LinuxI2CDevice>,
     ) -> Result<Inky1608, Error> {
         // Get eeprom info first so resolution and colour-type can be auto detected.
-        // (Actually it seems that the eeprom reported resolution isn't correct, so it
-        //  really always needs to be specified.)
         let dev = match i2c_bus {
             Some(d) => d,
             None => LinuxI2CDevice::new("/dev/i2c-1", EEP_ADDRESS)?,
@@ -111,7 +165,7 @@ impl Inky1608 {
         let eep_type = EEPType::new(dev)?;
         
         match eep_type.display_variant {
-            10 | 11 | 12 => (),
+            10..=12 => (),
             _ => panic!("This driver is not compatible with your board.")
         };
         
@@ -122,7 +176,7 @@ impl Inky1608 {
         
         let (cols, rows, rotation, offset_x, offset_y) = match res {
             (250, 122) => Ok((136, 250, -90, 0, 6)),
-            _ => Err(Error::new(ErrorKind::Other, "invalid resolution")),
+            _ => Err(Error::other("invalid resolution")),
         }?;
         
         let (r_cols, r_rows) = match rotation {
@@ -130,30 +184,23 @@ impl Inky1608 {
             _ => (cols, rows)
         };
         
-        let col = match colour {
+        let colour = match colour {
             Some(c) => c,
-            None => eep_type.colour_name()
+            None => Colour::try_from(eep_type.colour)?
         };
 
-        let colour = match col {
-            "red" => Ok(Colour::Red),
-            "black" => Ok(Colour::Black),
-            "yellow" => Ok(Colour::Yellow),
-            _ => Err(Error::new(ErrorKind::Other, "invalid colour")),
-        }?;
+        if colour == Colour::White {
+            return Err(Error::other("invalid colour"));
+        }
+        if colour == Colour::Gray2 && !gray2_supported(eep_type.colour) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                format!("gray2 needs a black panel, but the EEPROM reports {}", eep_type.colour_name())));
+        }
 
+        // Use SpiConfig::open() to pass in a device on another bus or speed.
         let spibus = match spidev {
             Some(b) => b,
-            None => {
-                let mut spi = Spidev::open(format!("/dev/spidev0.{}", cs_channel))?;
-                let options = SpidevOptions::new()
-                    .bits_per_word(8)
-                    .max_speed_hz(488_000)
-                    .mode(SpiModeFlags::SPI_MODE_0)
-                    .build();
-                spi.configure(&options)?;
-                spi
-            }
+            None => SpiConfig::default().open(cs_channel)?
         };
 
         let inky = Inky1608 {
@@ -167,8 +214,11 @@ impl Inky1608 {
             offset_x,
             offset_y,
             colour,
-            border_colour: Colour::White,
+            border_colour: Some(Colour::White),
             lut: get_lut(&colour),
+            vcom: Vcom::default(),
+            timing: PanelTiming::default(),
+            voltages: DriveVoltages::default(),
             cs_channel,
             dc_pin: Pin::new(dc_pin),
             reset_pin: Pin::new(reset_pin),
@@ -177,105 +227,300 @@ impl Inky1608 {
             v_flip,
             eeprom: eep_type,
             spidev: spibus,
-            framebuffer: vec![false; (cols * rows).into()]
+            spi_chunk_size: spi::detect_chunk_size(),
+            framebuffer: vec![false; (cols * rows).into()],
+            accent: vec![false; (cols * rows).into()],
+            last_frame: None,
+            last_accent: vec![false; (cols * rows).into()],
+            refreshing: None,
+            initialised: false,
+            exported: false
         };
         Ok(inky)
     }
 
     fn setup(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
-        self.busy_pin.export()?;
-        while !self.busy_pin.is_exported() {}
-        self.busy_pin.set_direction(Direction::In)?;
-
-        self.dc_pin.export()?;
-        while !self.dc_pin.is_exported() {}
-        self.dc_pin.set_direction(Direction::Out)?;
-        self.dc_pin.set_value(0)?;
-
-        self.reset_pin.export()?;
-        while !self.reset_pin.is_exported() {}
-        self.reset_pin.set_direction(Direction::Out)?;
-        self.reset_pin.set_value(1)?;
-
-        let mut delay = Duration::new(0, 500_000);
-        self.reset_pin.set_value(0)?;
-        sleep(delay);
-        self.reset_pin.set_value(1)?;
-        sleep(delay);
-        self.send_command(0x12, None)?; // Soft reset
-        delay = Duration::new(1, 0);
-        sleep(delay);
-        self.busy_wait()
+        if !self.exported {
+            export_pins(&self.dc_pin, &self.reset_pin, &self.busy_pin)?;
+            self.exported = true;
+        }
+        hardware_reset(&self.spidev, &self.dc_pin, &self.reset_pin, self.spi_chunk_size)?;
+        self.busy_wait()?;
+        for (command, data) in self.init_commands() {
+            self.send_command(command, Some(&data))?;
+        }
+        self.initialised = true;
+        Ok(())
+    }
+
+    /// Reset and reconfigure the controller. This happens automatically on the
+    /// first flush; call it to recover a panel that has stopped responding.
+    #[allow(dead_code)]
+    pub fn reinit(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+        self.wait_refresh()?;
+        self.initialised = false;
+        self.setup()
+    }
+    
+    /// Put the controller into deep sleep. The next flush resets and
+    /// reconfigures it.
+    #[allow(dead_code)]
+    pub fn sleep(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+        self.wait_refresh()?;
+        if self.initialised {
+            self.send_command(DEEP_SLEEP, Some(&[0x01]))?;
+            self.initialised = false;
+        }
+        Ok(())
+    }
+
+    /// Put the panel to sleep, unexport the GPIOs and hand back the SPI and
+    /// I2C devices.
+    #[allow(dead_code)]
+    pub fn release(mut self) -> Result<(Spidev, LinuxI2CDevice), linux_embedded_hal::sysfs_gpio::Error> {
+        self.shutdown()?;
+        let i2c = self.eeprom.take_device().ok_or_else(|| linux_embedded_hal::sysfs_gpio::Error::Unexpected(
+            "I2C device already released".to_string()))?;
+        let spidev = Arc::clone(&self.spidev);
+        drop(self);
+        let spidev = Arc::try_unwrap(spidev)
+            .map_err(|_| linux_embedded_hal::sysfs_gpio::Error::Unexpected("SPI device is still in use".to_string()))?
+            .into_inner()
+            .map_err(|_| linux_embedded_hal::sysfs_gpio::Error::Unexpected("SPI device lock poisoned".to_string()))?;
+        Ok((spidev, i2c))
+    }
+
+    fn shutdown(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+        self.sleep()?;
+        self.unexport()
+    }
+
+    fn unexport(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+        if self.exported {
+            gpio::release(&self.busy_pin)?;
+            gpio::release(&self.dc_pin)?;
+            gpio::release(&self.reset_pin)?;
+            self.exported = false;
+        }
+        Ok(())
     }
     
     #[allow(dead_code)]
-    fn update(&mut self, buf_a: Vec<u8>, buf_b: Vec<u8>, busy_wait: bool) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
-        self.setup()?;
+    fn update(&mut self, buf_a: Vec<u8>, buf_b: Vec<u8>, lut: &[u8; 30], busy_wait: bool) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+        // Resetting the controller part way through a refresh leaves the panel
+        // half drawn, so let any active refresh finish first.
+        self.wait_refresh()?;
+        if !self.initialised {
+            self.setup()?;
+        }
         
+        for (command, data) in self.frame_commands(buf_a, buf_b, lut) {
+            self.send_command(command, Some(&data))?;
+        }
+        
+        if busy_wait {
+            self.busy_wait()?;
+        }
+        self.send_command(MASTER_ACTIVATE, None)?;
+        self.refreshing = Some(Instant::now());
+        Ok(())
+    }
+
+    // The command sequence that configures the panel after a reset. These
+    // settings survive between refreshes so only need sending once.
+    fn init_commands(&self) -> Vec<(u8, Vec<u8>)> {
+        let mut commands = vec![];
         let mut packed_height = vec![((self.rows - 1) & 0xff) as u8, ((self.rows - 1) >> 8) as u8];
 
         let mut temp = packed_height.clone();
-        temp.push(0x00 as u8);
-        self.send_command(DRIVER_CONTROL, Some(&temp as &[u8]))?;  // Gate setting
+        temp.push(0x00u8);
+        commands.push((DRIVER_CONTROL, temp));  // Gate setting
 
-        self.send_command(WRITE_DUMMY, Some(&[0x1B]))?;    // Set dummy line period
-        self.send_command(WRITE_GATELINE, Some(&[0x0B]))?;    // Set Line Width
+        commands.append(&mut self.timing.commands());
+        commands.append(&mut self.voltages.commands());
 
-        self.send_command(DATA_MODE, Some(&[0x03]))?;    // Data entry squence (scan direction leftward and downward)
-        self.send_command(SET_RAMXPOS, Some(&[0x00, ((self.cols / 8) - 1) as u8]))?;    // Set ram X start and end position
-        let mut temp = vec![0x00 as u8, 0x00 as u8];
+        commands.push((DATA_MODE, vec![0x03]));    // Data entry squence (scan direction leftward and downward)
+        commands.push((SET_RAMXPOS, vec![0x00, ((self.cols / 8) - 1) as u8]));    // Set ram X start and end position
+        let mut temp = vec![0x00u8, 0x00u8];
         temp.append(&mut packed_height);
-        self.send_command(SET_RAMYPOS, Some(&temp))?;    // Set ram Y start and end position
+        commands.push((SET_RAMYPOS, temp));    // Set ram Y start and end position
 
-        self.send_command(WRITE_VCOM, Some(&[0x70]))?;    // VCOM Voltage
+        commands.push((WRITE_VCOM, vec![self.vcom.register()]));    // VCOM Voltage
+        commands
+    }
 
+    // The command sequence that loads a waveform and both RAM buffers, ready
+    // for MASTER_ACTIVATE.
+    fn frame_commands(&self, buf_a: Vec<u8>, buf_b: Vec<u8>, lut: &[u8; 30]) -> Vec<(u8, Vec<u8>)> {
+        vec![
+            (WRITE_LUT, lut.to_vec()),   // Write LUT DATA
+            (WRITE_BORDER, vec![border_setting(self.border_colour)]),
+            // Set RAM address to 0, 0
+            (SET_RAMXCOUNT, vec![0x00]),
+            (SET_RAMYCOUNT, vec![0x00, 0x00]),
+            // Do RAM B/W
+            (WRITE_RAM, buf_a),
+            // & Yellow/Red
+            (WRITE_ALTRAM, buf_b),
+        ]
+    }
+    
+    /// Full refresh: every pixel is driven through the complete waveform.
+    /// Returns once the refresh has started; see `wait_refresh()`.
+    #[allow(dead_code)]
+    pub fn flush(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+        let (buf_a, buf_b) = self.pack_planes(&self.framebuffer, &self.accent);
         let lut = self.lut;
-        self.send_command(WRITE_LUT, Some(&lut))?;   // Write LUT DATA
-
-        match self.border_colour {
-          Colour::Black => self.send_command(WRITE_BORDER, Some(&[0x00]))?,     // GS Transition Define A + VSS + LUT0
-          Colour::Red => if self.colour == Colour::Red { self.send_command(WRITE_BORDER, Some(&[0b00000110]))? },   // Fix Level Define A + VSH2 + LUT3
-          Colour::Yellow => if self.colour == Colour::Yellow { self.send_command(WRITE_BORDER, Some(&[0b00001111]))? },   // GS Transition Define A + VSH2 + LUT3
-          Colour::White => self.send_command(WRITE_BORDER, Some(&[0b00000001]))?,   // GS Transition Define A + VSH2 + LUT1
-          _ => ()
+        self.update(buf_a, buf_b, &lut, true)?;
+        self.sent();
+        Ok(())
+    }
+
+    /// Partial (differential) refresh: the last transmitted frame is sent as the
+    /// "old" image so the controller only drives pixels that have changed. Falls
+    /// back to a full refresh if nothing has been sent to the panel yet.
+    #[allow(dead_code)]
+    pub fn flush_partial(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+        if self.colour != Colour::Black {
+            return Err(linux_embedded_hal::sysfs_gpio::Error::Unsupported(
+                "partial refresh is only supported on black panels".to_string()));
+        }
+        let buf_b = match &self.last_frame {
+            Some(frame) => self.pack_planes(frame, &self.last_accent).0,
+            None => return self.flush()
         };
-        
-        // Set RAM address to 0, 0
-        self.send_command(SET_RAMXCOUNT, Some(&[0x00]))?;
-        self.send_command(SET_RAMYCOUNT, Some(&[0x00, 0x00]))?;
+        let (buf_a, _) = self.pack_planes(&self.framebuffer, &self.accent);
+        self.update(buf_a, buf_b, &LUT_PARTIAL, true)?;
+        self.sent();
+        Ok(())
+    }
 
-        // Do RAM B/W
-        self.send_command(WRITE_RAM, Some(&buf_a))?;
-        // & Yellow/Red
-        self.send_command(WRITE_ALTRAM, Some(&buf_b))?;
-        
-        if busy_wait {
+    /// Start a full refresh without waiting for it to complete. Fails if the
+    /// panel is still busy with a previous refresh rather than interrupting it.
+    #[allow(dead_code)]
+    pub fn begin_refresh(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+        if self.is_refreshing()? {
+            return Err(linux_embedded_hal::sysfs_gpio::Error::Unexpected(
+                "refresh already in progress".to_string()));
+        }
+        self.flush()
+    }
+
+    /// Whether a refresh started by `flush()`/`begin_refresh()` is still running.
+    #[allow(dead_code)]
+    pub fn is_refreshing(&mut self) -> Result<bool, linux_embedded_hal::sysfs_gpio::Error> {
+        if let Some(started) = self.refreshing {
+            if started.elapsed() >= BUSY_SETTLE && self.busy_pin.get_value()? == 0 {
+                self.refreshing = None;
+            }
+        }
+        Ok(self.refreshing.is_some())
+    }
+
+    /// Block until the current refresh (if any) has completed.
+    #[allow(dead_code)]
+    pub fn wait_refresh(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+        if let Some(started) = self.refreshing {
+            sleep(BUSY_SETTLE.saturating_sub(started.elapsed()));
             self.busy_wait()?;
+            self.refreshing = None;
         }
-        self.send_command(MASTER_ACTIVATE, None)?;
         Ok(())
     }
-    
+
+    /// Rectangles (in display coordinates) covering every pixel that differs
+    /// between the framebuffer and the last frame sent to the panel. If nothing
+    /// has been sent yet the whole display is returned.
     #[allow(dead_code)]
-    pub fn flush(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
-        // Need to convert the framebuffer which is Vec<bool> into Vec<u8> where
-        // each bit is a pixel.
-        let mut destvec : Vec<u8> = vec![];
-        for bytes in self.framebuffer.chunks(8) {
-            let mut dest : u8 = 0x0;
-            for byte in bytes {
-                if *byte {
-                    dest = (dest << 1) | 0x1;
-                } else {
-                    dest = dest << 1;
-                }
+    pub fn diff(&self) -> Vec<Rectangle> {
+        match &self.last_frame {
+            Some(frame) => {
+                let mut regions = diff_frames(frame, &self.framebuffer, self.r_cols, self.r_rows,
+                    |x, y| self.framebuffer_index(x, y));
+                regions.extend(diff_frames(&self.last_accent, &self.accent, self.r_cols, self.r_rows,
+                    |x, y| self.framebuffer_index(x, y)));
+                regions
             }
-            destvec.push(dest ^ 0xff);
+            None => vec![Rectangle::new(Point::zero(), self.size())]
+        }
+    }
+
+    /// Drawing target for the panel's accent colour (red or yellow): `On` pixels
+    /// are shown in the accent colour, `Off` pixels revert to white. Drawing on
+    /// the display itself replaces any accent at those pixels. On black panels
+    /// accent pixels are shown black, and in Gray2 mode light grey.
+    #[allow(dead_code)]
+    pub fn accent_layer(&mut self) -> AccentLayer<'_> {
+        AccentLayer { inky: self }
+    }
+
+    /// Drawing target for the four grey levels of a `Colour::Gray2` display.
+    /// On other panels the greys are thresholded to black and white. Gray2
+    /// is experimental: its waveform hasn't been measured on a panel.
+    #[allow(dead_code)]
+    pub fn grey_layer(&mut self) -> GreyLayer<'_> {
+        GreyLayer { inky: self }
+    }
+
+    // RAM contents for a frame: the black/white plane, with accent pixels
+    // forced white, and the accent plane, where a set bit is accent. Black
+    // panels have no accent so those pixels are drawn black instead. In Gray2
+    // mode the planes are sent as they are.
+    fn pack_planes(&self, frame: &[bool], accent: &[bool]) -> (Vec<u8>, Vec<u8>) {
+        if self.colour == Colour::Black {
+            let black: Vec<bool> = frame.iter().zip(accent).map(|(&b, &a)| b || a).collect();
+            (pack_frame(&black), vec![0x0; black.len() / 8])
+        } else if self.colour == Colour::Gray2 {
+            (pack_frame(frame), pack_bits(accent))
+        } else {
+            let black: Vec<bool> = frame.iter().zip(accent).map(|(&b, &a)| b && !a).collect();
+            (pack_frame(&black), pack_bits(accent))
         }
-        self.update(destvec, vec![0x0; (self.cols * self.rows).into()], true)?;
+    }
+
+    // Record the framebuffer as what the panel is now showing.
+    fn sent(&mut self) {
+        self.last_frame = Some(self.framebuffer.clone());
+        self.last_accent = self.accent.clone();
+    }
+
+    // Map a display (rotated) coordinate to its offset in the framebuffer.
+    fn framebuffer_index(&self, x: u16, y: u16) -> usize {
+        let x = if self.h_flip { self.r_cols - 1 - x } else { x };
+        let y = if self.v_flip { self.r_rows - 1 - y } else { y };
+        physical_index(self.cols, self.rows, self.rotation, x, y)
+    }
+
+    /// Rotate the display by 0, 90, 180 or 270 (or -90) degrees. The default
+    /// for the 250x122 panel is -90, which is landscape with the connector on
+    /// the left. This doesn't change what has already been drawn.
+    #[allow(dead_code)]
+    pub fn set_rotation(&mut self, rotation: i16) -> Result<(), Error> {
+        self.rotation = match rotation {
+            0 | 90 | 180 => rotation,
+            -90 | 270 => -90,
+            _ => return Err(Error::new(ErrorKind::InvalidInput, format!("invalid rotation {}", rotation)))
+        };
+        (self.r_cols, self.r_rows) = self.rotated_dimensions(self.rotation);
         Ok(())
     }
-    
+
+    // Width and height in display coordinates with the given rotation.
+    pub(crate) fn rotated_dimensions(&self, rotation: i16) -> (u16, u16) {
+        match rotation {
+            90 | -90 | 270 => (self.rows, self.cols),
+            _ => (self.cols, self.rows)
+        }
+    }
+
+    /// Mirror the display horizontally and/or vertically (after rotation). The
+    /// `h_flip` and `v_flip` passed to `new()` set the initial values.
+    #[allow(dead_code)]
+    pub fn set_flip(&mut self, h_flip: bool, v_flip: bool) {
+        self.h_flip = h_flip;
+        self.v_flip = v_flip;
+    }
+
     fn busy_wait(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
         let delay = Duration::new(0, 10_000);
         while self.busy_pin.get_value()? != 0 {
@@ -283,13 +528,24 @@ impl Inky1608 {
         }
         Ok(())
     }
+
+    // As busy_wait(), giving up after `timeout`. Returns how long BUSY took
+    // to drop, or None if it didn't.
+    fn busy_wait_timeout(&mut self, timeout: Duration) -> Result<Option<Duration>, linux_embedded_hal::sysfs_gpio::Error> {
+        let start = Instant::now();
+        // BUSY can take a moment to assert after a command.
+        sleep(Duration::from_millis(1));
+        while self.busy_pin.get_value()? != 0 {
+            if start.elapsed() > timeout {
+                return Ok(None);
+            }
+            sleep(Duration::new(0, 10_000));
+        }
+        Ok(Some(start.elapsed()))
+    }
     
     fn send_command(&mut self, command: u8, data: Option<&[u8]>) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
-        self.spi_write(SPI_COMMAND, &[command])?;
-        match data {
-            Some(d) => self.spi_write(SPI_DATA, d),
-            None => Ok(())
-        }
+        send_spi_command(&self.spidev, &self.dc_pin, self.spi_chunk_size, command, data)
     }
     
     #[allow(dead_code)]
@@ -297,57 +553,440 @@ impl Inky1608 {
         self.spi_write(SPI_DATA, data)
     }
     
+    /// Set the border colour used from the next refresh. Red and yellow are only
+    /// available on panels of that colour.
+    #[allow(dead_code)]
+    pub fn set_border(&mut self, colour: Colour) -> Result<(), Error> {
+        if !border_supported(self.colour, colour) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                format!("{:?} border is not supported by a {:?} panel", colour, self.colour)));
+        }
+        self.border_colour = Some(colour);
+        Ok(())
+    }
+
+    /// Leave the border floating (high impedance) so it is not driven at all.
+    #[allow(dead_code)]
+    pub fn set_border_floating(&mut self) {
+        self.border_colour = None;
+    }
+
+    /// Refresh only the border, leaving the image as last sent (any drawing
+    /// since the last flush is not shown). On black panels the partial waveform
+    /// is used so the image doesn't flash. If nothing has been sent since the
+    /// display was opened, black panels read the image back from the
+    /// controller's RAM; red and yellow panels can't read back their accent
+    /// colour, so this fails rather than redrawing the panel without it.
     #[allow(dead_code)]
-    pub fn set_border(&mut self, colour: Colour) {
-        self.border_colour = match colour {
-            Colour::Black => Colour::Black,
-            Colour::White => Colour::White,
-            Colour::Red => Colour::Red,
-            Colour::Yellow => Colour::Yellow,
-            _ => self.border_colour
+    pub fn refresh_border(&mut self) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+        let last_frame = match self.last_frame.take() {
+            Some(frame) => frame,
+            // The controller's RAM keeps the image through resets and deep sleep.
+            None if self.colour == Colour::Black => {
+                self.last_accent = vec![false; self.framebuffer.len()];
+                self.read_ram()?.to_frame()
+            }
+            None => return Err(linux_embedded_hal::sysfs_gpio::Error::Unsupported(format!(
+                "nothing has been sent to the panel yet, and a {} panel's image can't be read back", self.colour)))
+        };
+        let (frame, accent) = self.pack_planes(&last_frame, &self.last_accent);
+        self.last_frame = Some(last_frame);
+        if self.colour == Colour::Black {
+            self.update(frame.clone(), frame, &LUT_PARTIAL, true)
+        } else {
+            let lut = self.lut;
+            self.update(frame, accent, &lut, true)
         }
     }
     
+    /// Change the VCOM voltage, e.g. to tune contrast for a particular panel.
+    #[allow(dead_code)]
+    pub fn set_vcom(&mut self, vcom: Vcom) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+        self.vcom = vcom;
+        if self.initialised {
+            self.wait_refresh()?;
+            self.send_command(WRITE_VCOM, Some(&[vcom.register()]))?;
+        }
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn vcom(&self) -> Vcom {
+        self.vcom
+    }
+
+    /// Change the gate timing. Takes effect on the next flush, which
+    /// reinitialises the controller.
+    #[allow(dead_code)]
+    pub fn set_timing(&mut self, timing: PanelTiming) {
+        self.timing = timing;
+        self.initialised = false;
+    }
+
+    /// Change the driving voltages. Takes effect on the next flush, which
+    /// reinitialises the controller.
+    #[allow(dead_code)]
+    pub fn set_drive_voltages(&mut self, voltages: DriveVoltages) {
+        self.voltages = voltages;
+        self.initialised = false;
+    }
+
+    /// Have the controller measure the panel's optimum VCOM, holding the sense
+    /// conditions for `seconds` (1-16), then use the result. The panel should be
+    /// showing a stable image. The next flush reinitialises the controller.
+    #[allow(dead_code)]
+    pub fn calibrate_vcom(&mut self, seconds: u8) -> Result<Vcom, linux_embedded_hal::sysfs_gpio::Error> {
+        if !(1..=16).contains(&seconds) {
+            return Err(linux_embedded_hal::sysfs_gpio::Error::Unexpected(
+                "VCOM sense duration must be 1-16 seconds".to_string()));
+        }
+        self.wait_refresh()?;
+        if !self.initialised {
+            self.setup()?;
+        }
+        let commands = vcom_sense_commands(seconds);
+        for (command, data) in &commands {
+            self.send_command(*command, Some(data))?;
+            if matches!(*command, MASTER_ACTIVATE | VCOM_SENSE) {
+                sleep(BUSY_SETTLE);
+                self.busy_wait()?;
+            }
+        }
+        // The sensed value is latched into the VCOM register, which is reported
+        // by the OTP register read.
+        let vcom = Vcom::from_register(self.read_otp()?.vcom_register)?;
+        if !keeps_setup(&commands) {
+            self.initialised = false;
+        }
+        // Sent by the next flush's setup if the controller needs configuring again.
+        self.set_vcom(vcom)?;
+        Ok(vcom)
+    }
+
+    /// Read back the black/white RAM, e.g. to check what the panel was sent.
+    #[allow(dead_code)]
+    pub fn read_ram(&mut self) -> Result<DisplayRam, linux_embedded_hal::sysfs_gpio::Error> {
+        self.wait_refresh()?;
+        if !self.initialised {
+            self.setup()?;
+        }
+        self.send_command(SET_RAMXCOUNT, Some(&[0x00]))?;
+        self.send_command(SET_RAMYCOUNT, Some(&[0x00, 0x00]))?;
+        let len = usize::from(self.cols / 8) * usize::from(self.rows);
+        // The first byte clocked out after READ_RAM is a dummy.
+        let mut data = self.read_command(READ_RAM, len + 1)?;
+        data.remove(0);
+        Ok(DisplayRam { cols: self.cols, rows: self.rows, data })
+    }
+
+    /// Read the OTP registers: the VCOM value and the factory waveform.
+    #[allow(dead_code)]
+    pub fn read_otp(&mut self) -> Result<Otp, linux_embedded_hal::sysfs_gpio::Error> {
+        self.wait_refresh()?;
+        if !self.initialised {
+            self.setup()?;
+        }
+        Ok(Otp::from_bytes(&self.read_command(READ_OTP, readback::OTP_LEN)?))
+    }
+
+    /// Override the largest single SPI transfer, which is otherwise read from
+    /// spidev's `bufsiz` parameter.
+    #[allow(dead_code)]
+    pub fn set_spi_chunk_size(&mut self, chunk_size: usize) {
+        self.spi_chunk_size = chunk_size.max(1);
+    }
+
+    /// The SPI handle for this display's chip select. Don't pass it to `new()`
+    /// for another display: every command would reach both panels.
+    #[allow(dead_code)]
+    pub fn spi(&self) -> SharedSpi {
+        Arc::clone(&self.spidev)
+    }
+
     #[allow(dead_code)]
     pub fn ident(&self) {
         println!("{}", self);
     }
     
     fn spi_write(&mut self, dc: u8, data: &[u8]) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
-        self.dc_pin.set_value(dc)?;
-        if cfg!(target_os = "linux") {
-            for data_chunk in data.chunks(SPI_CHUNK_SIZE) {
-                self.spidev.write(data_chunk)?;
+        write_spi(&self.spidev, &self.dc_pin, self.spi_chunk_size, dc, data)
+    }
+
+    fn read_command(&mut self, command: u8, len: usize) -> Result<Vec<u8>, linux_embedded_hal::sysfs_gpio::Error> {
+        read_spi(&self.spidev, &self.dc_pin, self.spi_chunk_size, command, len)
+    }
+}
+
+// The free functions below only need the SPI handle and pins (not the whole
+// driver) so that they can also be run on a blocking thread by the async API.
+
+fn write_spi(spidev: &Mutex<Spidev>, dc_pin: &Pin, chunk_size: usize, dc: u8, data: &[u8]) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+    // DC may be shared with displays on other SPI devices, so hold its lock
+    // until the transfer is done.
+    let dc_lock = gpio::dc_lock(dc_pin)?;
+    let _dc = dc_lock.lock().map_err(|_| linux_embedded_hal::sysfs_gpio::Error::Unexpected(
+        "DC pin lock poisoned".to_string()))?;
+    let mut spidev = spidev.lock().map_err(|_| linux_embedded_hal::sysfs_gpio::Error::Unexpected(
+        "SPI device lock poisoned".to_string()))?;
+    dc_pin.set_value(dc)?;
+    if cfg!(target_os = "linux") {
+        for data_chunk in data.chunks(chunk_size) {
+            spidev.write(data_chunk)?;
+        }
+    } else {
+        spidev.write(data)?;
+    }
+    Ok(())
+}
+
+// Send a command then read `len` bytes back. The SSD1608 only has a single
+// bidirectional data line, so the bus is switched to 3-wire mode, and slowed
+// down, for the read.
+fn read_spi(spidev: &Mutex<Spidev>, dc_pin: &Pin, chunk_size: usize, command: u8, len: usize) -> Result<Vec<u8>, linux_embedded_hal::sysfs_gpio::Error> {
+    let dc_lock = gpio::dc_lock(dc_pin)?;
+    let _dc = dc_lock.lock().map_err(|_| linux_embedded_hal::sysfs_gpio::Error::Unexpected(
+        "DC pin lock poisoned".to_string()))?;
+    let mut spidev = spidev.lock().map_err(|_| linux_embedded_hal::sysfs_gpio::Error::Unexpected(
+        "SPI device lock poisoned".to_string()))?;
+    dc_pin.set_value(SPI_COMMAND)?;
+    spidev.write(&[command])?;
+    let write_speed = spidevioctl::get_max_speed_hz(spidev.0.as_raw_fd())?;
+    spidev.configure(&SpidevOptions::new()
+        .mode(SpiModeFlags::SPI_MODE_0 | SpiModeFlags::SPI_3WIRE)
+        .max_speed_hz(write_speed.min(spi::MAX_READ_SPEED_HZ))
+        .build())?;
+    dc_pin.set_value(SPI_DATA)?;
+    let mut data = vec![0u8; len];
+    let read = data.chunks_mut(chunk_size).try_for_each(|chunk| spidev.read_exact(chunk));
+    spidev.configure(&SpidevOptions::new().mode(SpiModeFlags::SPI_MODE_0).max_speed_hz(write_speed).build())?;
+    read?;
+    Ok(data)
+}
+
+fn send_spi_command(spidev: &Mutex<Spidev>, dc_pin: &Pin, chunk_size: usize, command: u8, data: Option<&[u8]>) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+    write_spi(spidev, dc_pin, chunk_size, SPI_COMMAND, &[command])?;
+    match data {
+        Some(d) => write_spi(spidev, dc_pin, chunk_size, SPI_DATA, d),
+        None => Ok(())
+    }
+}
+
+// Pins already claimed by another display are left configured as they are.
+fn export_pins(dc_pin: &Pin, reset_pin: &Pin, busy_pin: &Pin) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+    if gpio::claim(busy_pin)? {
+        busy_pin.set_direction(Direction::In)?;
+    }
+
+    if gpio::claim(dc_pin)? {
+        dc_pin.set_direction(Direction::Out)?;
+        dc_pin.set_value(0)?;
+    }
+
+    if gpio::claim(reset_pin)? {
+        reset_pin.set_direction(Direction::Out)?;
+        reset_pin.set_value(1)?;
+    }
+    Ok(())
+}
+
+// Pulse RESET and issue a soft reset. The caller still needs to wait for BUSY.
+fn hardware_reset(spidev: &Mutex<Spidev>, dc_pin: &Pin, reset_pin: &Pin, chunk_size: usize) -> Result<(), linux_embedded_hal::sysfs_gpio::Error> {
+    let mut delay = Duration::new(0, 500_000);
+    reset_pin.set_value(0)?;
+    sleep(delay);
+    reset_pin.set_value(1)?;
+    sleep(delay);
+    send_spi_command(spidev, dc_pin, chunk_size, SW_RESET, None)?; // Soft reset
+    delay = Duration::new(1, 0);
+    sleep(delay);
+    Ok(())
+}
+
+// The command sequence that has the controller sense the panel's VCOM for
+// `seconds`. BUSY needs waiting for after MASTER_ACTIVATE and VCOM_SENSE.
+fn vcom_sense_commands(seconds: u8) -> Vec<(u8, Vec<u8>)> {
+    vec![
+        // Enable clock and analog so the sense circuit is powered.
+        (DISP_CTRL2, vec![0xC0]),
+        (MASTER_ACTIVATE, vec![]),
+        (VCOM_DURATION, vec![seconds - 1]),
+        (VCOM_SENSE, vec![]),
+    ]
+}
+
+// Whether the controller is still configured as setup() left it after
+// `commands`. Refreshes rely on DISP_CTRL2 keeping its reset value, so after
+// anything that changes it, resets or sleeps the controller, the next flush
+// has to run setup() again.
+fn keeps_setup(commands: &[(u8, Vec<u8>)]) -> bool {
+    !commands.iter().any(|(command, _)| matches!(*command, DISP_CTRL2 | SW_RESET | DEEP_SLEEP))
+}
+
+// The Gray2 waveform is only meant for black glass, so refuse it on panels
+// the EEPROM says are red or yellow.
+fn gray2_supported(eeprom_colour: u8) -> bool {
+    !matches!(Colour::try_from(eeprom_colour), Ok(Colour::Red | Colour::Yellow))
+}
+
+fn border_supported(panel: Colour, border: Colour) -> bool {
+    match border {
+        Colour::Black | Colour::White => true,
+        Colour::Red => panel == Colour::Red || panel == Colour::RedHt,
+        Colour::Yellow => panel == Colour::Yellow,
+        Colour::RedHt | Colour::Gray2 => false
+    }
+}
+
+// Offset in a cols x rows framebuffer of the display coordinate x, y for a
+// given rotation. Columns count from 0, so the last is cols - 1.
+fn physical_index(cols: u16, rows: u16, rotation: i16, x: u16, y: u16) -> usize {
+    let (col, row) = match rotation {
+        -90 => (cols - 1 - y, x),
+        90 => (y, rows - 1 - x),
+        180 => (cols - 1 - x, rows - 1 - y),
+        _ => (x, y)
+    };
+    usize::from(row) * usize::from(cols) + usize::from(col)
+}
+
+// The WRITE_BORDER value for a border colour (None is floating).
+fn border_setting(border: Option<Colour>) -> u8 {
+    match border {
+        Some(Colour::Black) => 0x00,     // GS Transition Define A + VSS + LUT0
+        Some(Colour::Red) => 0b00000110,   // Fix Level Define A + VSH2 + LUT3
+        Some(Colour::Yellow) => 0b00001111,   // GS Transition Define A + VSH2 + LUT3
+        Some(Colour::White) | Some(Colour::RedHt) | Some(Colour::Gray2) => 0b00000001,   // GS Transition Define A + VSH2 + LUT1
+        None => 0b11000000   // HiZ
+    }
+}
+
+// Convert a framebuffer of pixels into the panel's RAM format, where each bit
+// is a pixel and a set bit is white.
+fn pack_frame(frame: &[bool]) -> Vec<u8> {
+    pack_bits(frame).iter().map(|byte| byte ^ 0xff).collect()
+}
+
+// Pack pixels eight to a byte, most significant bit first, a set bit for true.
+fn pack_bits(frame: &[bool]) -> Vec<u8> {
+    let mut destvec : Vec<u8> = vec![];
+    for bytes in frame.chunks(8) {
+        let mut dest : u8 = 0x0;
+        for byte in bytes {
+            if *byte {
+                dest = (dest << 1) | 0x1;
+            } else {
+                dest <<= 1;
             }
-        } else {
-            self.spidev.write(data)?;
         }
-        Ok(())
+        destvec.push(dest);
+    }
+    destvec
+}
+
+// Compare two frames over a width x height display and return the changed
+// regions, one bounding rectangle per run of consecutive changed rows.
+fn diff_frames<F>(old: &[bool], new: &[bool], width: u16, height: u16, index: F) -> Vec<Rectangle>
+where
+    F: Fn(u16, u16) -> usize,
+{
+    let mut regions = vec![];
+    let mut current: Option<(u16, u16, u16)> = None; // (top, min x, max x)
+    for y in 0..height {
+        let changed: Vec<u16> = (0..width).filter(|&x| old[index(x, y)] != new[index(x, y)]).collect();
+        match (changed.first(), changed.last()) {
+            (Some(&first), Some(&last)) => {
+                current = Some(match current {
+                    Some((top, min_x, max_x)) => (top, min_x.min(first), max_x.max(last)),
+                    None => (y, first, last)
+                });
+            }
+            _ => {
+                if let Some((top, min_x, max_x)) = current.take() {
+                    regions.push(region(top, y - 1, min_x, max_x));
+                }
+            }
+        }
+    }
+    if let Some((top, min_x, max_x)) = current {
+        regions.push(region(top, height - 1, min_x, max_x));
+    }
+    regions
+}
+
+fn region(top: u16, bottom: u16, min_x: u16, max_x: u16) -> Rectangle {
+    Rectangle::new(
+        Point::new(min_x.into(), top.into()),
+        Size::new((max_x - min_x + 1).into(), (bottom - top + 1).into())
+    )
+}
+
+// Waveform for partial refreshes: pixels whose old and new values match are
+// left alone, so only changed pixels flicker.
+const LUT_PARTIAL: [u8; 30] = [
+    0x10, 0x18, 0x18, 0x08, 0x18, 0x18, 0x08, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x13, 0x14, 0x44, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+];
+
+// Full-refresh waveform. Bytes 0-19 are the voltage sequence for each phase
+// and 20-29 the phase lengths. This is the table Pimoroni's SSD1608 driver
+// uses for black, red and yellow panels alike, and the only one known to work
+// on real glass, so the accent panels use it too.
+const LUT_BLACK: [u8; 30] = [
+    0x02, 0x02, 0x01, 0x11, 0x12, 0x12, 0x22, 0x22, 0x66, 0x69,
+    0x69, 0x59, 0x58, 0x99, 0x99, 0x88, 0x00, 0x00, 0x00, 0x00,
+    0xF8, 0xB4, 0x13, 0x51, 0x35, 0x51, 0x51, 0x19, 0x01, 0x00
+];
+
+// Four grey levels. The RAM and ALTRAM bits of a pixel select one of four
+// columns in each voltage byte (bits 1-0 RAM 0/ALTRAM 0, 3-2 RAM 1/ALTRAM 0,
+// 5-4 RAM 0/ALTRAM 1, 7-6 RAM 1/ALTRAM 1). The first two columns are the
+// black and white columns of LUT_BLACK; dark grey follows black but misses its
+// last drive phase and light grey follows white but misses its last two.
+// Unlike LUT_BLACK this is not a vendor table and hasn't been measured on a
+// panel, which is why Gray2 is documented as experimental.
+const LUT_GRAY2: [u8; 30] = [
+    0x22, 0x22, 0x11, 0x11, 0x22, 0x22, 0x22, 0x22, 0x66, 0x99,
+    0x99, 0x99, 0x88, 0x99, 0x09, 0x08, 0x00, 0x00, 0x00, 0x00,
+    0xF8, 0xB4, 0x13, 0x51, 0x35, 0x51, 0x51, 0x19, 0x01, 0x00
+];
+
+// The (black, grey) framebuffer bits for a grey level from 0 (black) to 3
+// (white). They are sent inverted in RAM and as-is in ALTRAM, so black and
+// white have the same RAM bits as on a black panel and a zero ALTRAM bit.
+fn grey_bits(luma: u8) -> (bool, bool) {
+    match luma {
+        0 => (true, false),
+        1 => (true, true),
+        2 => (false, true),
+        _ => (false, false)
     }
 }
 
+// Every colour except Gray2 shares LUT_BLACK. Pimoroni's driver uses that one
+// table for black, red and yellow SSD1608 panels, and there are no measured
+// red, yellow or high-temperature waveforms to use instead.
 fn get_lut(colour: &Colour) -> [u8; 30] {
     match colour {
-        Colour::Black => [
-            0x02, 0x02, 0x01, 0x11, 0x12, 0x12, 0x22, 0x22, 0x66, 0x69,
-            0x69, 0x59, 0x58, 0x99, 0x99, 0x88, 0x00, 0x00, 0x00, 0x00,
-            0xF8, 0xB4, 0x13, 0x51, 0x35, 0x51, 0x51, 0x19, 0x01, 0x00
-        ],
-        Colour::Red => [
-            0x02, 0x02, 0x01, 0x11, 0x12, 0x12, 0x22, 0x22, 0x66, 0x69,
-            0x69, 0x59, 0x58, 0x99, 0x99, 0x88, 0x00, 0x00, 0x00, 0x00,
-            0xF8, 0xB4, 0x13, 0x51, 0x35, 0x51, 0x51, 0x19, 0x01, 0x00
-        ],
-        Colour::Yellow => [
-            0x02, 0x02, 0x01, 0x11, 0x12, 0x12, 0x22, 0x22, 0x66, 0x69,
-            0x69, 0x59, 0x58, 0x99, 0x99, 0x88, 0x00, 0x00, 0x00, 0x00,
-            0xF8, 0xB4, 0x13, 0x51, 0x35, 0x51, 0x51, 0x19, 0x01, 0x00
-        ],
-        _ => [ // default to black
-            0x02, 0x02, 0x01, 0x11, 0x12, 0x12, 0x22, 0x22, 0x66, 0x69,
-            0x69, 0x59, 0x58, 0x99, 0x99, 0x88, 0x00, 0x00, 0x00, 0x00,
-            0xF8, 0xB4, 0x13, 0x51, 0x35, 0x51, 0x51, 0x19, 0x01, 0x00
-        ],
+        Colour::Gray2 => LUT_GRAY2,
+        _ => LUT_BLACK
+    }
+}
+
+impl Drop for Inky1608 {
+    fn drop(&mut self) {
+        // Nothing useful can be done with an error here. A stuck BUSY line
+        // mustn't hang the process, so if the refresh doesn't finish in time
+        // skip the deep sleep and just release the pins.
+        if let Some(started) = self.refreshing {
+            sleep(BUSY_SETTLE.saturating_sub(started.elapsed()));
+            if !matches!(self.busy_wait_timeout(DROP_BUSY_TIMEOUT), Ok(Some(_))) {
+                let _ = self.unexport();
+                return;
+            }
+            self.refreshing = None;
+        }
+        let _ = self.shutdown();
     }
 }
 
@@ -361,17 +1000,79 @@ impl DrawTarget for Inky1608 {
     {
         for Pixel(coord, colour) in pixels.into_iter() {
             if coord.x >= 0 && coord.x < self.r_cols.into() && coord.y >= 0 && coord.y < self.r_rows.into() {
-                let offset : u16 = match self.rotation {
-                    90 | -90 => coord.x as u16 * self.cols + (self.cols - coord.y as u16),
-                    _ => coord.y as u16 * self.r_cols + coord.x as u16
-                };
-                self.framebuffer[offset as usize] = colour.is_on();
+                let offset = self.framebuffer_index(coord.x as u16, coord.y as u16);
+                self.framebuffer[offset] = colour.is_on();
+                self.accent[offset] = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The accent colour plane of an `Inky1608`, see `Inky1608::accent_layer()`.
+pub struct AccentLayer<'a> {
+    inky: &'a mut Inky1608,
+}
+
+impl DrawTarget for AccentLayer<'_> {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let size = self.inky.size();
+        for Pixel(coord, colour) in pixels.into_iter() {
+            if coord.x >= 0 && coord.x < size.width as i32 && coord.y >= 0 && coord.y < size.height as i32 {
+                let offset = self.inky.framebuffer_index(coord.x as u16, coord.y as u16);
+                self.inky.accent[offset] = colour.is_on();
+                self.inky.framebuffer[offset] = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for AccentLayer<'_> {
+    fn size(&self) -> Size {
+        self.inky.size()
+    }
+}
+
+/// The grey levels of an `Inky1608`, see `Inky1608::grey_layer()`.
+pub struct GreyLayer<'a> {
+    inky: &'a mut Inky1608,
+}
+
+impl DrawTarget for GreyLayer<'_> {
+    type Color = Gray2;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let size = self.inky.size();
+        let grey = self.inky.colour == Colour::Gray2;
+        for Pixel(coord, colour) in pixels.into_iter() {
+            if coord.x >= 0 && coord.x < size.width as i32 && coord.y >= 0 && coord.y < size.height as i32 {
+                let offset = self.inky.framebuffer_index(coord.x as u16, coord.y as u16);
+                let (black, accent) = if grey { grey_bits(colour.luma()) } else { (colour.luma() < 2, false) };
+                self.inky.framebuffer[offset] = black;
+                self.inky.accent[offset] = accent;
             }
         }
         Ok(())
     }
 }
 
+impl OriginDimensions for GreyLayer<'_> {
+    fn size(&self) -> Size {
+        self.inky.size()
+    }
+}
+
 impl OriginDimensions for Inky1608 {
     fn size(&self) -> Size {
         Size::new(self.r_cols.into(), self.r_rows.into())
@@ -381,12 +1082,7 @@ impl OriginDimensions for Inky1608 {
 impl std::fmt::Display for Inky1608 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Inky is {} rows x {} cols\nrotation {}, colour {}\nEeprom info: {}", self.rows, self.cols, self.rotation,
-            match self.colour {
-                Colour::Black => "black",
-                Colour::Red => "red",
-                Colour::Yellow => "yellow",
-                _ => "unknown!!"
-            }, self.eeprom
+            self.colour, self.eeprom
         )
     }
 }
@@ -398,21 +1094,145 @@ mod tests {
     #[test]
     #[should_panic]
     fn bad_resolution() {
-        Inky1608::new(Some((27, 10)), Some("black"), 8, 22, 27, 17, false, false, None, None).expect("bad resolution");
+        Inky1608::new(Some((27, 10)), Some(Colour::Black), 8, 22, 27, 17, false, false, None, None).expect("bad resolution");
     }
 
     #[test]
     #[should_panic]
     fn bad_colour() {
-        Inky1608::new(Some((212, 104)), Some("purple"), 8, 22, 27, 17, false, false, None, None).expect("bad colour");
+        Inky1608::new(Some((212, 104)), Some(Colour::White), 8, 22, 27, 17, false, false, None, None).expect("bad colour");
     }
 
     #[test]
     fn new() {
-        let inky = Inky1608::new(Some((212, 104)), Some("black"), 0, 22, 27, 17, false, false, None, None).expect("inky new");
+        let inky = Inky1608::new(Some((212, 104)), Some(Colour::Black), 0, 22, 27, 17, false, false, None, None).expect("inky new");
         assert_eq!(inky.cols, 104);
         assert_eq!(inky.rows, 212);
         assert_eq!(inky.rotation, -90);
         assert_eq!(inky.colour, Colour::Black);
     }
+
+    #[test]
+    fn panels_share_full_waveform() {
+        let luts = [LUT_BLACK, LUT_GRAY2, LUT_PARTIAL];
+        for (i, a) in luts.iter().enumerate() {
+            assert_eq!(a.len(), 30);
+            for b in &luts[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+        assert_eq!(get_lut(&Colour::Red), LUT_BLACK);
+        assert_eq!(get_lut(&Colour::Yellow), LUT_BLACK);
+        assert_eq!(get_lut(&Colour::RedHt), LUT_BLACK);
+        assert_eq!(get_lut(&Colour::White), LUT_BLACK);
+        assert_eq!(get_lut(&Colour::Gray2), LUT_GRAY2);
+    }
+
+    #[test]
+    fn gray2_encoding() {
+        // Black and white are driven exactly as on a black panel.
+        for (grey, black) in LUT_GRAY2[..20].iter().zip(&LUT_BLACK[..20]) {
+            assert_eq!(grey & 0x0f, black & 0x0f);
+        }
+        let levels: Vec<(bool, bool)> = (0..4).map(grey_bits).collect();
+        assert_eq!(levels[0], (true, false));
+        assert_eq!(levels[3], (false, false));
+        // Every level has a distinct pair of bits.
+        for (i, a) in levels.iter().enumerate() {
+            assert!(!levels[i + 1..].contains(a));
+        }
+    }
+
+    #[test]
+    fn gray2_needs_black_glass() {
+        assert!(gray2_supported(1));
+        assert!(!gray2_supported(2));
+        assert!(!gray2_supported(3));
+        // An unreadable colour byte isn't evidence of an accent panel.
+        assert!(gray2_supported(0));
+    }
+
+    #[test]
+    fn vcom_sense_needs_setup() {
+        let sense = vcom_sense_commands(4);
+        assert_eq!(sense[0], (DISP_CTRL2, vec![0xC0]));
+        assert_eq!(sense[2], (VCOM_DURATION, vec![3]));
+        // Sensing leaves DISP_CTRL2 at clock and analog only, which would stop
+        // every later MASTER_ACTIVATE updating the panel, so the next flush
+        // resets and configures the controller again.
+        assert!(!keeps_setup(&sense));
+        assert!(keeps_setup(&[(WRITE_VCOM, vec![0x70]), (WRITE_LUT, LUT_BLACK.to_vec())]));
+    }
+
+    #[test]
+    fn border_validation() {
+        assert!(border_supported(Colour::Black, Colour::White));
+        assert!(border_supported(Colour::Red, Colour::Black));
+        assert!(border_supported(Colour::Red, Colour::Red));
+        assert!(!border_supported(Colour::Black, Colour::Red));
+        assert!(!border_supported(Colour::Red, Colour::Yellow));
+        assert!(!border_supported(Colour::Red, Colour::RedHt));
+        assert!(border_supported(Colour::RedHt, Colour::Red));
+        assert_eq!(border_setting(None), 0xC0);
+        assert_eq!(border_setting(Some(Colour::Black)), 0x00);
+    }
+
+    #[test]
+    fn rotations() {
+        let (cols, rows) = (8u16, 4u16);
+        for rotation in [0, 90, 180, -90] {
+            let (width, height) = if rotation % 180 == 0 { (cols, rows) } else { (rows, cols) };
+            let mut seen = vec![false; usize::from(cols * rows)];
+            for y in 0..height {
+                for x in 0..width {
+                    let i = physical_index(cols, rows, rotation, x, y);
+                    assert!(!seen[i], "rotation {} maps two pixels to {}", rotation, i);
+                    seen[i] = true;
+                }
+            }
+        }
+        assert_eq!(physical_index(cols, rows, -90, 0, 0), 7);
+        assert_eq!(physical_index(cols, rows, 90, 0, 0), 24);
+        assert_eq!(physical_index(cols, rows, 180, 0, 0), 31);
+    }
+
+    #[test]
+    fn rotated_corners() {
+        // The 250x122 panel: 136 columns of RAM, 250 rows, rotated -90.
+        let (cols, rows) = (136u16, 250u16);
+        let len = usize::from(cols) * usize::from(rows);
+        // Mapping y to `cols - y` shifted every pixel one column, so the top
+        // right pixel landed one past the end of the framebuffer.
+        let old = |x: u16, y: u16| usize::from(x) * usize::from(cols) + usize::from(cols - y);
+        assert_eq!(old(249, 0), len);
+        assert_eq!(physical_index(cols, rows, -90, 249, 0), len - 1);
+        assert_eq!(physical_index(cols, rows, -90, 0, 121), 14);
+        assert_eq!(old(0, 121), 15);
+    }
+
+    #[test]
+    fn pack_frame_bits() {
+        let mut frame = vec![false; 16];
+        frame[0] = true;
+        frame[15] = true;
+        assert_eq!(pack_frame(&frame), vec![0x7f, 0xfe]);
+        assert_eq!(pack_bits(&frame), vec![0x80, 0x01]);
+    }
+
+    #[test]
+    fn diff_frames_regions() {
+        let (w, h) = (8u16, 6u16);
+        let index = |x: u16, y: u16| usize::from(y * w + x);
+        let old = vec![false; 48];
+        assert!(diff_frames(&old, &old, w, h, index).is_empty());
+
+        let mut new = old.clone();
+        new[index(2, 1)] = true;
+        new[index(5, 2)] = true;
+        new[index(7, 5)] = true;
+        assert_eq!(diff_frames(&old, &new, w, h, index), vec![
+            Rectangle::new(Point::new(2, 1), Size::new(4, 2)),
+            Rectangle::new(Point::new(7, 5), Size::new(1, 1)),
+        ]);
+    }
 }
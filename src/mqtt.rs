@@ -0,0 +1,281 @@
+// Showing messages from an MQTT broker on the display.
+//
+// Three topics are subscribed to, by default under the prefix "inky":
+//
+//     inky/image    An image file (PNG, JPEG, BMP or PBM/PGM/PPM), drawn
+//                   over the whole display.
+//     inky/text     UTF-8 text, centred in the largest font that fits.
+//     inky/command  "clear", "sleep", or "border <colour>" where the colour
+//                   is a colour name or "floating".
+//
+// Topics may contain wildcards, e.g. "sensors/+/status". If a message matches
+// more than one, image is tried first, then text, then command.
+//
+// With a local mosquitto broker the display can be driven with e.g.
+//
+//     mosquitto_pub -t inky/text -m 'Back at 5'
+//     mosquitto_pub -t inky/image -f logo.png
+//
+// Messages are shown in the order they arrive, each waiting for the previous
+// refresh to finish. The ignored `broker` test runs the same round trip
+// against a local broker and the attached display.
+use super::*;
+use crate::layout::{HAlign, TextBox, VAlign};
+use embedded_graphics::mono_font::ascii::{FONT_10X20, FONT_6X10, FONT_9X15};
+use image::DynamicImage;
+use rumqttc::{matches, Client, Connection, Event, MqttOptions, Packet, QoS};
+use std::time::Duration;
+
+/// Port MQTT brokers listen on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 1883;
+
+// Largest message accepted, which needs to allow for an uncompressed image.
+const MAX_PACKET: usize = 4 * 1024 * 1024;
+
+/// The topics listened to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topics {
+    /// Image files to show.
+    pub image: String,
+    /// Text to show.
+    pub text: String,
+    /// "clear", "sleep" and "border <colour>" commands.
+    pub command: String,
+}
+
+impl Topics {
+    /// `<prefix>/image`, `<prefix>/text` and `<prefix>/command`.
+    pub fn new(prefix: &str) -> Topics {
+        let prefix = prefix.trim_end_matches('/');
+        Topics {
+            image: format!("{}/image", prefix),
+            text: format!("{}/text", prefix),
+            command: format!("{}/command", prefix),
+        }
+    }
+}
+
+impl Default for Topics {
+    fn default() -> Topics {
+        Topics::new("inky")
+    }
+}
+
+// A message decoded from one of the topics.
+#[derive(Debug)]
+enum Message {
+    Image(DynamicImage),
+    Text(String),
+    Clear,
+    Sleep,
+    Border(Option<Colour>),
+}
+
+/// A connection to a broker, showing what is published on `Topics`.
+pub struct Subscriber {
+    client: Client,
+    connection: Connection,
+    topics: Topics,
+    inky: Inky1608,
+}
+
+impl Subscriber {
+    /// Connect to the broker at `host`:`port` as `client_id`. The connection
+    /// is made, and remade if lost, by `run()`.
+    pub fn new(host: &str, port: u16, client_id: &str, topics: Topics, inky: Inky1608) -> Subscriber {
+        let mut options = MqttOptions::new(client_id, host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_max_packet_size(MAX_PACKET, MAX_PACKET);
+        let (client, connection) = Client::new(options, 10);
+        Subscriber { client, connection, topics, inky }
+    }
+
+    /// Show messages as they arrive. Bad messages and connection failures are
+    /// reported on stderr; this only returns if the client is shut down.
+    pub fn run(mut self) -> Result<(), Error> {
+        for event in self.connection.iter() {
+            match event {
+                // Subscribe on every connection, as a new session starts empty.
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    for topic in [&self.topics.image, &self.topics.text, &self.topics.command] {
+                        self.client.try_subscribe(topic.as_str(), QoS::AtLeastOnce).map_err(Error::other)?;
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let shown = parse(&self.topics, &publish.topic, &publish.payload, self.inky.size())
+                        .and_then(|message| show(&mut self.inky, message));
+                    if let Err(e) = shown {
+                        eprintln!("inky-mqtt: {}: {}", publish.topic, e);
+                    }
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    eprintln!("inky-mqtt: {}", e);
+                    std::thread::sleep(Duration::from_secs(5));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Decode a message published on `topic`.
+// Images are limited to what can be scaled down for a display of `size`.
+fn parse(topics: &Topics, topic: &str, payload: &[u8], size: Size) -> Result<Message, Error> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidInput, message);
+    if matches(topic, &topics.image) {
+        return Ok(Message::Image(images::decode(payload, None, size)?));
+    }
+    let text = std::str::from_utf8(payload).map_err(|e| invalid(format!("not UTF-8: {}", e)))?;
+    if matches(topic, &topics.text) {
+        return Ok(Message::Text(text.trim_end().to_string()));
+    }
+    if !matches(topic, &topics.command) {
+        return Err(invalid("not subscribed to this topic".to_string()));
+    }
+    let mut words = text.split_whitespace();
+    let message = match (words.next(), words.next()) {
+        (Some("clear"), None) => Message::Clear,
+        (Some("sleep"), None) => Message::Sleep,
+        (Some("border"), Some("floating")) => Message::Border(None),
+        (Some("border"), Some(colour)) => Message::Border(Some(colour.parse()?)),
+        _ => return Err(invalid(format!("unknown command \"{}\"", text.trim()))),
+    };
+    if words.next().is_some() {
+        return Err(invalid(format!("unknown command \"{}\"", text.trim())));
+    }
+    Ok(message)
+}
+
+// Show a message on the display and wait for the refresh to finish.
+fn show(inky: &mut Inky1608, message: Message) -> Result<(), Error> {
+    let gpio = |e: linux_embedded_hal::sysfs_gpio::Error| Error::other(e.to_string());
+    match message {
+        Message::Image(image) => {
+            inky.draw_image(&image, images::ScaleMode::Fit);
+            inky.flush().map_err(gpio)?;
+        }
+        Message::Text(text) => {
+            let _ = inky.clear(BinaryColor::Off);
+            let bounds = Rectangle::new(Point::zero(), inky.size());
+            let _ = TextBox::with_fonts(&text, bounds, &[&FONT_10X20, &FONT_9X15, &FONT_6X10])
+                .align(HAlign::Centre, VAlign::Middle)
+                .draw(inky);
+            inky.flush().map_err(gpio)?;
+        }
+        Message::Clear => {
+            let _ = inky.clear(BinaryColor::Off);
+            inky.flush().map_err(gpio)?;
+        }
+        Message::Sleep => inky.sleep().map_err(gpio)?,
+        Message::Border(colour) => {
+            match colour {
+                Some(colour) => inky.set_border(colour)?,
+                None => inky.set_border_floating(),
+            }
+            inky.refresh_border().map_err(gpio)?;
+        }
+    }
+    inky.wait_refresh().map_err(gpio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, RgbImage};
+    use std::io::Cursor;
+
+    const SIZE: Size = Size::new(250, 122);
+
+    #[test]
+    fn topics() {
+        let topics = Topics::new("site/pi4/");
+        assert_eq!(topics.image, "site/pi4/image");
+        assert_eq!(topics.command, "site/pi4/command");
+        assert_eq!(Topics::default().text, "inky/text");
+    }
+
+    #[test]
+    fn wildcards() {
+        let topics = Topics {
+            image: "screens/+/image".to_string(),
+            text: "sensors/+/status".to_string(),
+            command: "inky/#".to_string(),
+        };
+        assert!(matches!(parse(&topics, "sensors/shed/status", b"21C", SIZE), Ok(Message::Text(text)) if text == "21C"));
+        assert!(matches!(parse(&topics, "inky/pi4/command", b"clear", SIZE), Ok(Message::Clear)));
+        assert!(parse(&topics, "sensors/shed/battery", b"90%", SIZE).is_err());
+    }
+
+    #[test]
+    fn commands() {
+        let topics = Topics::default();
+        let command = |text: &str| parse(&topics, "inky/command", text.as_bytes(), SIZE);
+        assert!(matches!(command("clear\n"), Ok(Message::Clear)));
+        assert!(matches!(command("sleep"), Ok(Message::Sleep)));
+        assert!(matches!(command("border floating"), Ok(Message::Border(None))));
+        assert!(matches!(command("border Red"), Ok(Message::Border(Some(Colour::Red)))));
+        assert!(command("border purple").is_err());
+        assert!(command("clear now").is_err());
+        assert_eq!(command("dance").expect_err("unknown").kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn payloads() {
+        let topics = Topics::default();
+        assert!(matches!(parse(&topics, "inky/text", b"Back at 5\n", SIZE), Ok(Message::Text(text)) if text == "Back at 5"));
+        assert!(parse(&topics, "inky/text", &[0xff, 0xfe], SIZE).is_err());
+        assert!(parse(&topics, "inky/image", b"not an image", SIZE).is_err());
+        assert!(parse(&topics, "other/topic", b"clear", SIZE).is_err());
+
+        let mut png = vec![];
+        RgbImage::new(3, 2).write_to(&mut Cursor::new(&mut png), ImageFormat::Png).expect("encode");
+        match parse(&topics, "inky/image", &png, SIZE) {
+            Ok(Message::Image(image)) => assert_eq!((image.width(), image.height()), (3, 2)),
+            _ => panic!("expected an image"),
+        }
+
+        // Far larger than the display needs, so refused before decoding.
+        png.clear();
+        RgbImage::new(4001, 1).write_to(&mut Cursor::new(&mut png), ImageFormat::Png).expect("encode");
+        assert!(parse(&topics, "inky/image", &png, SIZE).is_err());
+    }
+
+    // Needs a broker on localhost:1883 and the display attached:
+    //     cargo test --features mqtt -- --ignored broker
+    #[test]
+    #[ignore]
+    fn broker() {
+        let id = format!("inky-test-{}", std::process::id());
+        let topics = Topics::new(&id);
+        let mut options = MqttOptions::new(&id, "localhost", DEFAULT_PORT);
+        options.set_max_packet_size(MAX_PACKET, MAX_PACKET);
+        let (client, mut connection) = Client::new(options, 10);
+        for topic in [&topics.image, &topics.text, &topics.command] {
+            client.subscribe(topic.as_str(), QoS::AtLeastOnce).expect("subscribe");
+        }
+        let mut png = vec![];
+        RgbImage::new(25, 12).write_to(&mut Cursor::new(&mut png), ImageFormat::Png).expect("encode");
+        client.publish(&topics.text, QoS::AtLeastOnce, false, "Back at 5\n").expect("publish");
+        client.publish(&topics.command, QoS::AtLeastOnce, false, "clear").expect("publish");
+        client.publish(&topics.image, QoS::AtLeastOnce, false, png).expect("publish");
+
+        let mut inky = Inky1608::new(None, None, 0, 22, 27, 17, false, false, None, None).expect("display");
+        let mut received = vec![];
+        for event in connection.iter() {
+            if let Event::Incoming(Packet::Publish(publish)) = event.expect("connection") {
+                let message = parse(&topics, &publish.topic, &publish.payload, inky.size()).expect("parse");
+                received.push(match &message {
+                    Message::Text(text) => text.clone(),
+                    Message::Image(image) => format!("{}x{}", image.width(), image.height()),
+                    other => format!("{:?}", other),
+                });
+                show(&mut inky, message).expect("show");
+                if received.len() == 3 {
+                    break;
+                }
+            }
+        }
+        assert_eq!(received, ["Back at 5", "Clear", "25x12"]);
+    }
+}
@@ -0,0 +1,97 @@
+// Drive timing and voltage configuration sent to the SSD1608 on initialisation.
+//
+// The defaults reproduce what the driver has always sent: the dummy line period
+// and gate line width suited to the Inky pHAT's glass, with everything else left
+// at the controller's power-on values. Other SSD1608 glass may need different
+// values from its datasheet.
+use super::{
+    BOOSTER_SOFT_START, GATE_SCAN_START, GATE_VOLTAGE, NON_OVERLAP, SOURCE_VOLTAGE,
+    WRITE_DUMMY, WRITE_GATELINE
+};
+
+/// Gate timing. `None` fields are not sent, leaving the controller default.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct PanelTiming {
+    /// Dummy line period in units of TGate (command 0x3A).
+    pub dummy_line_period: u8,
+    /// Gate line width (command 0x3B).
+    pub gate_line_width: u8,
+    /// Gate/source non-overlap period (command 0x0B).
+    pub non_overlap: Option<u8>,
+    /// First gate line to scan (command 0x0F).
+    pub gate_scan_start: Option<u16>,
+}
+
+impl Default for PanelTiming {
+    fn default() -> Self {
+        PanelTiming {
+            dummy_line_period: 0x1B,
+            gate_line_width: 0x0B,
+            non_overlap: None,
+            gate_scan_start: None,
+        }
+    }
+}
+
+impl PanelTiming {
+    pub(crate) fn commands(&self) -> Vec<(u8, Vec<u8>)> {
+        let mut commands = vec![
+            (WRITE_DUMMY, vec![self.dummy_line_period]),    // Set dummy line period
+            (WRITE_GATELINE, vec![self.gate_line_width]),    // Set Line Width
+        ];
+        if let Some(period) = self.non_overlap {
+            commands.push((NON_OVERLAP, vec![period]));
+        }
+        if let Some(line) = self.gate_scan_start {
+            commands.push((GATE_SCAN_START, vec![(line & 0xff) as u8, (line >> 8) as u8]));
+        }
+        commands
+    }
+}
+
+/// Driving voltages and booster settings, as raw register values from the
+/// SSD1608 datasheet. `None` fields leave the controller default.
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub struct DriveVoltages {
+    /// Gate driving voltage, VGH/VGL (command 0x03).
+    pub gate: Option<u8>,
+    /// Source driving voltage, VSH/VSL (command 0x04).
+    pub source: Option<u8>,
+    /// Booster soft-start phases A, B and C (command 0x0C).
+    pub booster_soft_start: Option<[u8; 3]>,
+}
+
+impl DriveVoltages {
+    pub(crate) fn commands(&self) -> Vec<(u8, Vec<u8>)> {
+        let mut commands = vec![];
+        if let Some(gate) = self.gate {
+            commands.push((GATE_VOLTAGE, vec![gate]));
+        }
+        if let Some(source) = self.source {
+            commands.push((SOURCE_VOLTAGE, vec![source]));
+        }
+        if let Some(phases) = self.booster_soft_start {
+            commands.push((BOOSTER_SOFT_START, phases.to_vec()));
+        }
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_commands() {
+        assert_eq!(PanelTiming::default().commands(), vec![(0x3A, vec![0x1B]), (0x3B, vec![0x0B])]);
+        assert!(DriveVoltages::default().commands().is_empty());
+    }
+
+    #[test]
+    fn configured_commands() {
+        let timing = PanelTiming { gate_scan_start: Some(0x0102), non_overlap: Some(0x10), ..Default::default() };
+        assert_eq!(timing.commands()[2..], [(0x0B, vec![0x10]), (0x0F, vec![0x02, 0x01])]);
+        let voltages = DriveVoltages { gate: Some(0xEA), source: Some(0x0A), booster_soft_start: Some([0xD7, 0xD6, 0x9D]) };
+        assert_eq!(voltages.commands(), vec![(0x03, vec![0xEA]), (0x04, vec![0x0A]), (0x0C, vec![0xD7, 0xD6, 0x9D])]);
+    }
+}
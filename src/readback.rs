@@ -0,0 +1,83 @@
+// Typed results of reading data back from the controller, for diagnostics.
+use super::Vcom;
+
+/// Number of bytes returned by the OTP register read: the VCOM register
+/// followed by the waveform (LUT) area.
+pub(crate) const OTP_LEN: usize = 31;
+
+/// Contents of the controller's black/white RAM as read back over SPI.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DisplayRam {
+    /// Physical (unrotated) width of the RAM in pixels.
+    pub cols: u16,
+    /// Physical (unrotated) height of the RAM in pixels.
+    pub rows: u16,
+    /// Packed RAM bytes, one bit per pixel with a set bit meaning white.
+    pub data: Vec<u8>,
+}
+
+impl DisplayRam {
+    /// Whether the pixel at a physical column/row is black.
+    pub fn is_black(&self, col: u16, row: u16) -> bool {
+        let bit = usize::from(row) * usize::from(self.cols) + usize::from(col);
+        self.data[bit / 8] & (0x80 >> (bit % 8)) == 0
+    }
+
+    /// The RAM unpacked into the driver's framebuffer layout (true is black).
+    pub fn to_frame(&self) -> Vec<bool> {
+        let len = usize::from(self.cols) * usize::from(self.rows);
+        self.data.iter()
+            .flat_map(|byte| (0..8).map(move |bit| byte & (0x80 >> bit) == 0))
+            .take(len)
+            .collect()
+    }
+}
+
+/// The controller's OTP registers: the VCOM value and the factory waveform.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Otp {
+    /// Raw VCOM register value.
+    pub vcom_register: u8,
+    /// The factory look-up table, in the same format as `WRITE_LUT`.
+    pub lut: [u8; 30],
+}
+
+impl Otp {
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Otp {
+        let mut lut = [0u8; 30];
+        lut.copy_from_slice(&bytes[1..OTP_LEN]);
+        Otp { vcom_register: bytes[0], lut }
+    }
+
+    /// The VCOM value, if it is within the range the driver accepts.
+    pub fn vcom(&self) -> Option<Vcom> {
+        Vcom::from_register(self.vcom_register).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_ram_pixels() {
+        let ram = DisplayRam { cols: 8, rows: 2, data: vec![0x7f, 0xfe] };
+        assert!(ram.is_black(0, 0));
+        assert!(!ram.is_black(1, 0));
+        assert!(ram.is_black(7, 1));
+        let frame = ram.to_frame();
+        assert_eq!(frame.len(), 16);
+        assert_eq!(frame.iter().filter(|p| **p).count(), 2);
+    }
+
+    #[test]
+    fn otp_from_bytes() {
+        let mut bytes = vec![0x70];
+        bytes.extend(1..=30);
+        let otp = Otp::from_bytes(&bytes);
+        assert_eq!(otp.vcom(), Some(Vcom::default()));
+        assert_eq!(otp.lut[0], 1);
+        assert_eq!(otp.lut[29], 30);
+        assert_eq!(Otp { vcom_register: 0, lut: otp.lut }.vcom(), None);
+    }
+}
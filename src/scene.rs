@@ -0,0 +1,509 @@
+// Screens described in TOML or JSON, so they can be designed without writing Rust.
+//
+// A scene sets the rotation and border and lists elements drawn in order over
+// a white background:
+//
+//     rotation = 180
+//     border = "black"
+//
+//     [[elements]]
+//     type = "text"
+//     text = "Meeting room 2"
+//     font = ["10x20", "6x10"]
+//     align = "centre"
+//     height = 30
+//
+//     [[elements]]
+//     type = "qr"
+//     data = "https://example.com/book"
+//     x = 170
+//     y = 40
+//     width = 80
+//     height = 80
+use super::*;
+use crate::codes::{EccLevel, Qr};
+use crate::dither::Dither;
+use crate::images::{self, PanelImage, ScaleMode};
+use crate::layout::{HAlign, TextBox, VAlign};
+use embedded_graphics::{
+    mono_font::{ascii, MonoFont},
+    primitives::{Line, PrimitiveStyle},
+};
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Why a scene couldn't be loaded or shown.
+#[derive(Debug)]
+pub enum SceneError {
+    /// The file couldn't be read or isn't valid TOML/JSON for a scene.
+    Parse(String),
+    /// A top level setting (rotation or border) is invalid.
+    Setting(String),
+    /// An element is invalid. `index` counts elements from 0 in file order.
+    Element { index: usize, kind: &'static str, message: String },
+    /// Updating the display failed.
+    Display(linux_embedded_hal::sysfs_gpio::Error),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Parse(message) | SceneError::Setting(message) => f.write_str(message),
+            SceneError::Element { index, kind, message } => write!(f, "element {} ({}): {}", index, kind, message),
+            SceneError::Display(e) => write!(f, "display error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/// A screen: display settings plus the elements to draw.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scene {
+    /// Rotation in degrees, as for `Inky1608::set_rotation()`.
+    pub rotation: Option<i16>,
+    /// Border colour, or "floating".
+    pub border: Option<String>,
+    #[serde(default)]
+    pub elements: Vec<Element>,
+    // Directory that image paths are relative to.
+    #[serde(skip)]
+    base: PathBuf,
+}
+
+/// One thing drawn on the screen. Positions and sizes are in pixels in the
+/// rotated display's coordinates; areas default to the rest of the display.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Element {
+    /// Text wrapped in its area, in the largest of the fonts that fits.
+    Text {
+        text: String,
+        #[serde(default)]
+        x: i32,
+        #[serde(default)]
+        y: i32,
+        width: Option<u32>,
+        height: Option<u32>,
+        /// A font name such as "6x10" or "9x15-bold", or a list to choose from.
+        #[serde(default = "default_font")]
+        font: Fonts,
+        /// "left", "centre" or "right".
+        align: Option<String>,
+        /// "top", "middle" or "bottom".
+        valign: Option<String>,
+        colour: Option<String>,
+    },
+    /// An image file, scaled into its area.
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        x: i32,
+        #[serde(default)]
+        y: i32,
+        width: Option<u32>,
+        height: Option<u32>,
+        /// "fit", "fill" or "stretch".
+        scale: Option<String>,
+        /// A dithering method, see `dither::Dither`.
+        dither: Option<String>,
+    },
+    /// A rectangle outline, or filled.
+    Rect {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        #[serde(default)]
+        fill: bool,
+        #[serde(default = "default_stroke")]
+        stroke: u32,
+        colour: Option<String>,
+    },
+    Line {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+        #[serde(default = "default_stroke")]
+        stroke: u32,
+        colour: Option<String>,
+    },
+    /// A QR code fitted to its area.
+    Qr {
+        data: String,
+        #[serde(default)]
+        x: i32,
+        #[serde(default)]
+        y: i32,
+        width: Option<u32>,
+        height: Option<u32>,
+        /// Error correction level: "l", "m", "q" or "h".
+        ecc: Option<String>,
+    },
+}
+
+/// One font name or several.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Fonts {
+    One(String),
+    Many(Vec<String>),
+}
+
+fn default_font() -> Fonts {
+    Fonts::One("6x10".to_string())
+}
+
+fn default_stroke() -> u32 {
+    1
+}
+
+impl Scene {
+    /// Parse a scene from TOML.
+    pub fn from_toml(text: &str) -> Result<Scene, SceneError> {
+        toml::from_str(text).map_err(|e| SceneError::Parse(e.to_string()))
+    }
+
+    /// Parse a scene from JSON.
+    pub fn from_json(text: &str) -> Result<Scene, SceneError> {
+        serde_json::from_str(text).map_err(|e| SceneError::Parse(e.to_string()))
+    }
+
+    /// Load a .toml or .json scene file. Image paths are relative to the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| SceneError::Parse(format!("{}: {}", path.display(), e)))?;
+        let mut scene = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Scene::from_json(&text),
+            _ => Scene::from_toml(&text),
+        }.map_err(|e| SceneError::Parse(format!("{}: {}", path.display(), e)))?;
+        scene.base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(scene)
+    }
+
+    /// Check the scene can be shown on a `panel` coloured display of `size`
+    /// (after rotation), without loading images.
+    pub fn validate(&self, panel: Colour, size: Size) -> Result<(), SceneError> {
+        if let Some(rotation) = self.rotation {
+            if ![0, 90, 180, 270, -90].contains(&rotation) {
+                return Err(SceneError::Setting(format!("rotation {} is not a multiple of 90 degrees", rotation)));
+            }
+        }
+        match self.border.as_deref() {
+            None | Some("floating") => (),
+            Some(border) => {
+                let colour: Colour = border.parse().map_err(|e: Error| SceneError::Setting(format!("border: {}", e)))?;
+                if !border_supported(panel, colour) {
+                    return Err(SceneError::Setting(format!("border: a {} panel can't show {}", panel, colour)));
+                }
+            }
+        }
+        for (index, element) in self.elements.iter().enumerate() {
+            element.check(panel, size).map_err(|message| SceneError::Element { index, kind: element.kind(), message })?;
+        }
+        Ok(())
+    }
+
+    /// Validate the scene and draw it into the framebuffer, applying its
+    /// rotation and border. Use `render()` to show it too.
+    pub fn draw(&self, inky: &mut Inky1608) -> Result<(), SceneError> {
+        // Validate against the rotated size before changing the display, so a
+        // bad scene leaves it as it was.
+        let size = match self.rotation {
+            Some(rotation) => {
+                let (width, height) = inky.rotated_dimensions(rotation);
+                Size::new(width.into(), height.into())
+            }
+            None => inky.size(),
+        };
+        self.validate(inky.colour, size)?;
+        if let Some(rotation) = self.rotation {
+            inky.set_rotation(rotation).map_err(|e| SceneError::Setting(format!("rotation: {}", e)))?;
+        }
+        match self.border.as_deref() {
+            None => (),
+            Some("floating") => inky.set_border_floating(),
+            Some(border) => {
+                let colour = border.parse().map_err(|e: Error| SceneError::Setting(e.to_string()))?;
+                inky.set_border(colour).map_err(|e| SceneError::Setting(e.to_string()))?;
+            }
+        }
+        let _ = inky.clear(BinaryColor::Off);
+        for (index, element) in self.elements.iter().enumerate() {
+            element.draw(inky, &self.base).map_err(|message| SceneError::Element { index, kind: element.kind(), message })?;
+        }
+        Ok(())
+    }
+
+    /// Draw the scene and start a full refresh.
+    pub fn render(&self, inky: &mut Inky1608) -> Result<(), SceneError> {
+        self.draw(inky)?;
+        inky.flush().map_err(SceneError::Display)
+    }
+}
+
+impl Element {
+    fn kind(&self) -> &'static str {
+        match self {
+            Element::Text { .. } => "text",
+            Element::Image { .. } => "image",
+            Element::Rect { .. } => "rect",
+            Element::Line { .. } => "line",
+            Element::Qr { .. } => "qr",
+        }
+    }
+
+    // The element's colour, if it has one.
+    fn colour(&self) -> Option<&str> {
+        match self {
+            Element::Text { colour, .. } | Element::Rect { colour, .. } | Element::Line { colour, .. } => colour.as_deref(),
+            _ => None
+        }
+    }
+
+    // The area the element is drawn in, given the display size.
+    fn area(&self, size: Size) -> Option<Rectangle> {
+        let area = |x: i32, y: i32, width: Option<u32>, height: Option<u32>| {
+            let rest = Size::new((size.width as i32 - x).max(0) as u32, (size.height as i32 - y).max(0) as u32);
+            Rectangle::new(Point::new(x, y), Size::new(width.unwrap_or(rest.width), height.unwrap_or(rest.height)))
+        };
+        match *self {
+            Element::Text { x, y, width, height, .. }
+            | Element::Image { x, y, width, height, .. }
+            | Element::Qr { x, y, width, height, .. } => Some(area(x, y, width, height)),
+            Element::Rect { x, y, width, height, .. } => Some(area(x, y, Some(width), Some(height))),
+            Element::Line { .. } => None,
+        }
+    }
+
+    // Everything that can be checked without drawing.
+    fn check(&self, panel: Colour, size: Size) -> Result<(), String> {
+        if let Some(colour) = self.colour() {
+            let colour: Colour = colour.parse().map_err(|e: Error| e.to_string())?;
+            let shown = match panel {
+                Colour::Red | Colour::RedHt => colour != Colour::Yellow && colour != Colour::RedHt,
+                Colour::Yellow => colour != Colour::Red && colour != Colour::RedHt,
+                _ => colour == Colour::Black || colour == Colour::White,
+            };
+            if !shown {
+                return Err(format!("a {} panel can't show {}", panel, colour));
+            }
+        }
+        if let Some(area) = self.area(size) {
+            if area.size.width == 0 || area.size.height == 0 {
+                return Err("area is empty".to_string());
+            }
+            if area.intersection(&Rectangle::new(Point::zero(), size)).is_zero_sized() {
+                return Err(format!("{:?} is outside the {}x{} display", area, size.width, size.height));
+            }
+        }
+        match self {
+            Element::Text { font, align, valign, .. } => {
+                fonts(font)?;
+                h_align(align.as_deref())?;
+                v_align(valign.as_deref())?;
+            }
+            Element::Image { scale, dither, .. } => {
+                scale_mode(scale.as_deref())?;
+                dither_method(dither.as_deref())?;
+            }
+            Element::Qr { data, ecc, .. } => {
+                Qr::new(data.as_bytes(), ecc_level(ecc.as_deref())?).map_err(|e| e.to_string())?;
+            }
+            Element::Rect { .. } | Element::Line { .. } => ()
+        }
+        Ok(())
+    }
+
+    fn draw(&self, inky: &mut Inky1608, base: &Path) -> Result<(), String> {
+        let area = self.area(inky.size()).unwrap_or_default();
+        let (accent, on) = match self.colour().map(|c| c.parse::<Colour>()) {
+            Some(Ok(Colour::White)) => (false, BinaryColor::Off),
+            Some(Ok(Colour::Red | Colour::Yellow | Colour::RedHt)) => (true, BinaryColor::On),
+            _ => (false, BinaryColor::On),
+        };
+        // Drawing to the framebuffer is infallible.
+        match self {
+            Element::Text { text, font, align, valign, .. } => {
+                let fonts = fonts(font)?;
+                let text = TextBox::with_fonts(text, area, &fonts)
+                    .align(h_align(align.as_deref())?, v_align(valign.as_deref())?)
+                    .colour(on);
+                let _ = if accent { text.draw(&mut inky.accent_layer()) } else { text.draw(inky) };
+            }
+            Element::Image { path, scale, dither, .. } => {
+                let image = images::open(base.join(path)).map_err(|e| format!("{}: {}", path.display(), e))?;
+                PanelImage::dithered(&image, area.size, scale_mode(scale.as_deref())?, inky.colour, dither_method(dither.as_deref())?)
+                    .draw(inky, area.top_left);
+            }
+            Element::Rect { fill, stroke, .. } => {
+                let style = if *fill { PrimitiveStyle::with_fill(on) } else { PrimitiveStyle::with_stroke(on, *stroke) };
+                let rect = area.into_styled(style);
+                let _ = if accent { rect.draw(&mut inky.accent_layer()) } else { rect.draw(inky) };
+            }
+            Element::Line { x1, y1, x2, y2, stroke, .. } => {
+                let line = Line::new(Point::new(*x1, *y1), Point::new(*x2, *y2)).into_styled(PrimitiveStyle::with_stroke(on, *stroke));
+                let _ = if accent { line.draw(&mut inky.accent_layer()) } else { line.draw(inky) };
+            }
+            Element::Qr { data, ecc, .. } => {
+                let qr = Qr::new(data.as_bytes(), ecc_level(ecc.as_deref())?).map_err(|e| e.to_string())?;
+                let _ = qr.fit(area).draw(inky);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn fonts(fonts: &Fonts) -> Result<Vec<&'static MonoFont<'static>>, String> {
+    let names = match fonts {
+        Fonts::One(name) => std::slice::from_ref(name),
+        Fonts::Many(names) => names.as_slice(),
+    };
+    if names.is_empty() {
+        return Err("no font given".to_string());
+    }
+    names.iter().map(|name| font(name).ok_or_else(|| format!("unknown font \"{}\"", name))).collect()
+}
+
+fn font(name: &str) -> Option<&'static MonoFont<'static>> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "4x6" => &ascii::FONT_4X6,
+        "5x7" => &ascii::FONT_5X7,
+        "5x8" => &ascii::FONT_5X8,
+        "6x9" => &ascii::FONT_6X9,
+        "6x10" => &ascii::FONT_6X10,
+        "6x12" => &ascii::FONT_6X12,
+        "6x13" => &ascii::FONT_6X13,
+        "6x13-bold" => &ascii::FONT_6X13_BOLD,
+        "6x13-italic" => &ascii::FONT_6X13_ITALIC,
+        "7x13" => &ascii::FONT_7X13,
+        "7x13-bold" => &ascii::FONT_7X13_BOLD,
+        "7x13-italic" => &ascii::FONT_7X13_ITALIC,
+        "7x14" => &ascii::FONT_7X14,
+        "7x14-bold" => &ascii::FONT_7X14_BOLD,
+        "8x13" => &ascii::FONT_8X13,
+        "8x13-bold" => &ascii::FONT_8X13_BOLD,
+        "8x13-italic" => &ascii::FONT_8X13_ITALIC,
+        "9x15" => &ascii::FONT_9X15,
+        "9x15-bold" => &ascii::FONT_9X15_BOLD,
+        "9x18" => &ascii::FONT_9X18,
+        "9x18-bold" => &ascii::FONT_9X18_BOLD,
+        "10x20" => &ascii::FONT_10X20,
+        _ => return None
+    })
+}
+
+fn h_align(align: Option<&str>) -> Result<HAlign, String> {
+    match align {
+        None | Some("left") => Ok(HAlign::Left),
+        Some("centre") | Some("center") => Ok(HAlign::Centre),
+        Some("right") => Ok(HAlign::Right),
+        Some(other) => Err(format!("unknown align \"{}\"", other)),
+    }
+}
+
+fn v_align(align: Option<&str>) -> Result<VAlign, String> {
+    match align {
+        None | Some("top") => Ok(VAlign::Top),
+        Some("middle") => Ok(VAlign::Middle),
+        Some("bottom") => Ok(VAlign::Bottom),
+        Some(other) => Err(format!("unknown valign \"{}\"", other)),
+    }
+}
+
+fn scale_mode(scale: Option<&str>) -> Result<ScaleMode, String> {
+    scale.map_or(Ok(ScaleMode::Fit), |s| s.parse().map_err(|e: Error| e.to_string()))
+}
+
+fn dither_method(dither: Option<&str>) -> Result<Dither, String> {
+    dither.map_or(Ok(Dither::None), |s| s.parse().map_err(|e: Error| e.to_string()))
+}
+
+fn ecc_level(ecc: Option<&str>) -> Result<EccLevel, String> {
+    ecc.map_or(Ok(EccLevel::Medium), |s| s.parse().map_err(|e: Error| e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: Size = Size::new(250, 122);
+
+    const SCENE: &str = r#"
+        rotation = 180
+        border = "black"
+
+        [[elements]]
+        type = "text"
+        text = "Meeting room 2"
+        font = ["10x20", "6x10"]
+        align = "centre"
+        height = 30
+
+        [[elements]]
+        type = "rect"
+        x = 0
+        y = 32
+        width = 250
+        height = 2
+        fill = true
+        colour = "red"
+
+        [[elements]]
+        type = "qr"
+        data = "https://example.com/book"
+        x = 170
+        y = 40
+    "#;
+
+    #[test]
+    fn parse_toml() {
+        let scene = Scene::from_toml(SCENE).expect("parse");
+        assert_eq!(scene.rotation, Some(180));
+        assert_eq!(scene.elements.len(), 3);
+        assert_eq!(scene.elements[2].area(SIZE), Some(Rectangle::new(Point::new(170, 40), Size::new(80, 82))));
+        scene.validate(Colour::Red, SIZE).expect("valid on a red panel");
+        match scene.validate(Colour::Black, SIZE) {
+            Err(SceneError::Element { index: 1, kind: "rect", message }) => assert!(message.contains("red"), "{}", message),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_json() {
+        let scene = Scene::from_json(r#"{"elements": [
+            {"type": "line", "x1": 0, "y1": 0, "x2": 10, "y2": 10},
+            {"type": "text", "text": "hi", "font": "12x24"}
+        ]}"#).expect("parse");
+        let error = scene.validate(Colour::Black, SIZE).expect_err("bad font");
+        assert_eq!(error.to_string(), "element 1 (text): unknown font \"12x24\"");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(Scene::from_toml("[[elements]]\ntype = \"circle\""), Err(SceneError::Parse(_))));
+        assert!(matches!(Scene::from_toml("[[elements]]\ntype = \"line\"\nx1 = 0"), Err(SceneError::Parse(_))));
+        assert!(matches!(Scene::from_toml("colour = \"red\""), Err(SceneError::Parse(_))));
+    }
+
+    #[test]
+    fn validation() {
+        let check = |toml: &str| Scene::from_toml(toml).expect("parse").validate(Colour::Black, SIZE);
+        assert!(matches!(check("rotation = 45"), Err(SceneError::Setting(_))));
+        assert!(matches!(check("border = \"yellow\""), Err(SceneError::Setting(_))));
+        assert!(check("border = \"floating\"").is_ok());
+        let element = |toml: &str| match check(toml) {
+            Err(SceneError::Element { index, message, .. }) => Some((index, message)),
+            _ => None,
+        };
+        assert!(element("[[elements]]\ntype = \"rect\"\nx = 300\ny = 0\nwidth = 10\nheight = 10").expect("outside").1.contains("outside"));
+        assert!(element("[[elements]]\ntype = \"rect\"\nx = 0\ny = 0\nwidth = 0\nheight = 10").is_some());
+        assert!(element("[[elements]]\ntype = \"image\"\npath = \"a.png\"\nscale = \"zoom\"").is_some());
+        assert!(element("[[elements]]\ntype = \"qr\"\ndata = \"x\"\necc = \"z\"").is_some());
+        assert!(element("[[elements]]\ntype = \"text\"\ntext = \"x\"\nvalign = \"centre\"").is_some());
+    }
+}
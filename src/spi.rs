@@ -0,0 +1,80 @@
+// Opening and sizing the SPI device.
+use super::SharedSpi;
+use linux_embedded_hal::spidev::{SpiModeFlags, SpidevOptions};
+use linux_embedded_hal::Spidev;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+
+/// Fastest clock the SSD1608 supports for writes.
+pub const MAX_SPI_SPEED_HZ: u32 = 20_000_000;
+
+// Reads need a much slower clock than writes; the bus is slowed to this (if
+// it's faster) for the duration of a read.
+pub(crate) const MAX_READ_SPEED_HZ: u32 = 1_000_000;
+
+// Used when the spidev buffer size can't be read. This is the kernel default.
+const DEFAULT_CHUNK_SIZE: usize = 4096;
+const BUFSIZ_PATH: &str = "/sys/module/spidev/parameters/bufsiz";
+
+/// Which spidev device to open and how fast to clock it.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct SpiConfig {
+    /// SPI bus number, i.e. the X in `/dev/spidevX.Y`.
+    pub bus: u16,
+    pub speed_hz: u32,
+}
+
+impl Default for SpiConfig {
+    fn default() -> Self {
+        SpiConfig { bus: 0, speed_hz: 488_000 }
+    }
+}
+
+impl SpiConfig {
+    /// Open `/dev/spidev{bus}.{cs_channel}` with this configuration.
+    pub fn open(&self, cs_channel: u16) -> Result<SharedSpi, Error> {
+        if self.speed_hz == 0 || self.speed_hz > MAX_SPI_SPEED_HZ {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                format!("SPI speed {}Hz out of range (max {}Hz)", self.speed_hz, MAX_SPI_SPEED_HZ)));
+        }
+        let mut spi = Spidev::open(format!("/dev/spidev{}.{}", self.bus, cs_channel))?;
+        let options = SpidevOptions::new()
+            .bits_per_word(8)
+            .max_speed_hz(self.speed_hz)
+            .mode(SpiModeFlags::SPI_MODE_0)
+            .build();
+        spi.configure(&options)?;
+        Ok(Arc::new(Mutex::new(spi)))
+    }
+}
+
+/// The largest single transfer spidev accepts, from its `bufsiz` module parameter.
+pub fn detect_chunk_size() -> usize {
+    fs::read_to_string(BUFSIZ_PATH).ok()
+        .and_then(|s| parse_bufsiz(&s))
+        .unwrap_or(DEFAULT_CHUNK_SIZE)
+}
+
+fn parse_bufsiz(s: &str) -> Option<usize> {
+    s.trim().parse().ok().filter(|size| *size > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bufsiz() {
+        assert_eq!(parse_bufsiz("4096\n"), Some(4096));
+        assert_eq!(parse_bufsiz("65536"), Some(65536));
+        assert_eq!(parse_bufsiz("0"), None);
+        assert_eq!(parse_bufsiz("junk"), None);
+    }
+
+    #[test]
+    fn speed_limit() {
+        let config = SpiConfig { speed_hz: MAX_SPI_SPEED_HZ + 1, ..Default::default() };
+        assert_eq!(config.open(0).err().map(|e| e.kind()), Some(ErrorKind::InvalidInput));
+    }
+}
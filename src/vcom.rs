@@ -0,0 +1,78 @@
+// VCOM (common electrode) voltage setting for the SSD1608.
+use std::fmt;
+use std::io::{Error, ErrorKind};
+
+// Register values accepted by WRITE_VCOM: 0x08 (-0.2V) to 0x78 (-3.0V) in 25mV steps.
+const VCOM_MIN: u8 = 0x08;
+const VCOM_MAX: u8 = 0x78;
+const STEPS_PER_VOLT: f32 = 40.0;
+
+/// VCOM voltage. The SSD1608 accepts -0.2V to -3.0V in 25mV steps.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Vcom(u8);
+
+impl Vcom {
+    pub fn from_volts(volts: f32) -> Result<Vcom, Error> {
+        let steps = (-volts * STEPS_PER_VOLT).round();
+        if !(f32::from(VCOM_MIN)..=f32::from(VCOM_MAX)).contains(&steps) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                format!("VCOM {}V out of range (-0.2V to -3.0V)", volts)));
+        }
+        Ok(Vcom(steps as u8))
+    }
+
+    /// Validate a raw register value, e.g. one read back after VCOM sensing.
+    pub fn from_register(value: u8) -> Result<Vcom, Error> {
+        match value {
+            VCOM_MIN..=VCOM_MAX => Ok(Vcom(value)),
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("invalid VCOM register value 0x{:02X}", value)))
+        }
+    }
+
+    /// The (negative) voltage in volts.
+    pub fn volts(&self) -> f32 {
+        -f32::from(self.0) / STEPS_PER_VOLT
+    }
+
+    pub fn register(&self) -> u8 {
+        self.0
+    }
+}
+
+impl Default for Vcom {
+    // The value the driver has always used.
+    fn default() -> Self {
+        Vcom(0x70)
+    }
+}
+
+impl fmt::Display for Vcom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.3}V", self.volts())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vcom_volts() {
+        let vcom = Vcom::from_volts(-2.8).expect("vcom");
+        assert_eq!(vcom, Vcom::default());
+        assert_eq!(vcom.register(), 0x70);
+        assert_eq!(vcom.volts(), -2.8);
+        assert_eq!(Vcom::from_volts(-0.2).expect("vcom").register(), 0x08);
+        assert_eq!(format!("{}", Vcom::from_volts(-1.5).expect("vcom")), "-1.500V");
+    }
+
+    #[test]
+    fn vcom_out_of_range() {
+        assert!(Vcom::from_volts(-0.1).is_err());
+        assert!(Vcom::from_volts(-3.1).is_err());
+        assert!(Vcom::from_volts(1.5).is_err());
+        assert!(Vcom::from_volts(0.0).is_err());
+        assert!(Vcom::from_register(0x00).is_err());
+        assert!(Vcom::from_register(0x79).is_err());
+    }
+}
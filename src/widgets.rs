@@ -0,0 +1,545 @@
+// Widgets for status screens, and stack/grid layouts to arrange them.
+//
+// Widgets draw onto a `Canvas`, and a `Screen` copies the canvas to any
+// `DrawTarget<Color = BinaryColor>`, reporting which areas changed since the
+// last render so they can be shown with a partial refresh. Rebuild the widget
+// tree with new values for each frame; widgets are cheap to create.
+use super::diff_frames;
+use crate::codes::{EccLevel, Qr};
+use crate::layout::{centred, text_width, HAlign, TextBox, VAlign};
+use embedded_graphics::{
+    image::{Image, ImageRaw},
+    mono_font::{MonoFont, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, Polyline, PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+
+/// Something that can be drawn into a rectangle of a `Canvas`.
+pub trait Widget {
+    /// Draw the widget to fill `bounds` as best it can.
+    fn draw(&self, canvas: &mut Canvas, bounds: Rectangle);
+}
+
+/// An in-memory 1-bit image that widgets draw onto.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Canvas {
+    size: Size,
+    pixels: Vec<bool>,
+}
+
+impl Canvas {
+    /// A blank (all `Off`) canvas.
+    pub fn new(size: Size) -> Canvas {
+        Canvas { size, pixels: vec![false; (size.width * size.height) as usize] }
+    }
+
+    /// The colour at a point, or None if it is outside the canvas.
+    pub fn pixel(&self, point: Point) -> Option<BinaryColor> {
+        self.index(point).map(|i| BinaryColor::from(self.pixels[i]))
+    }
+
+    fn index(&self, point: Point) -> Option<usize> {
+        let (x, y) = (point.x, point.y);
+        if x >= 0 && y >= 0 && (x as u32) < self.size.width && (y as u32) < self.size.height {
+            Some(y as usize * self.size.width as usize + x as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, colour) in pixels {
+            if let Some(i) = self.index(point) {
+                self.pixels[i] = colour.is_on();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+/// Renders a widget tree to a display, remembering the last frame.
+#[derive(Debug, Default)]
+pub struct Screen {
+    last: Option<Canvas>,
+}
+
+impl Screen {
+    pub fn new() -> Screen {
+        Screen::default()
+    }
+
+    /// Draw `root` over the whole of `target` and return the rectangles that
+    /// differ from the previous render (everything, the first time).
+    pub fn render<D>(&mut self, root: &dyn Widget, target: &mut D) -> Result<Vec<Rectangle>, D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let area = target.bounding_box();
+        let mut canvas = Canvas::new(area.size);
+        root.draw(&mut canvas, Rectangle::new(Point::zero(), area.size));
+        let dirty = match &self.last {
+            Some(last) if last.size == canvas.size => {
+                let width = area.size.width as u16;
+                diff_frames(&last.pixels, &canvas.pixels, width, area.size.height as u16,
+                    |x, y| usize::from(y) * usize::from(width) + usize::from(x))
+                    .into_iter().map(|r| r.translate(area.top_left)).collect()
+            }
+            _ => vec![area]
+        };
+        target.fill_contiguous(&area, canvas.pixels.iter().map(|&on| BinaryColor::from(on)))?;
+        self.last = Some(canvas);
+        Ok(dirty)
+    }
+}
+
+/// How much of a stack's length a child takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Length {
+    /// A fixed number of pixels.
+    Fixed(u32),
+    /// A share of the space left after fixed children, by weight.
+    Fill(u32),
+}
+
+/// Children laid out in a row or column.
+pub struct Stack {
+    vertical: bool,
+    spacing: u32,
+    children: Vec<(Length, Box<dyn Widget>)>,
+}
+
+impl Stack {
+    /// Children side by side, left to right.
+    pub fn row(spacing: u32) -> Stack {
+        Stack { vertical: false, spacing, children: vec![] }
+    }
+
+    /// Children one above the other, top to bottom.
+    pub fn column(spacing: u32) -> Stack {
+        Stack { vertical: true, spacing, children: vec![] }
+    }
+
+    /// Add a child.
+    pub fn push(mut self, length: Length, child: impl Widget + 'static) -> Stack {
+        self.children.push((length, Box::new(child)));
+        self
+    }
+
+    // Pixel length of each child along an axis of `total` pixels.
+    fn lengths(&self, total: u32) -> Vec<u32> {
+        let gaps = self.spacing * (self.children.len() as u32).saturating_sub(1);
+        let fixed: u32 = self.children.iter().map(|(length, _)| match length {
+            Length::Fixed(n) => *n,
+            Length::Fill(_) => 0,
+        }).sum();
+        let weights: u32 = self.children.iter().map(|(length, _)| match length {
+            Length::Fixed(_) => 0,
+            Length::Fill(w) => *w,
+        }).sum();
+        let spare = total.saturating_sub(fixed + gaps);
+        let mut given = 0;
+        let mut seen = 0;
+        self.children.iter().map(|(length, _)| match length {
+            Length::Fixed(n) => *n,
+            Length::Fill(w) => {
+                // Hand out by cumulative weight so rounding never loses pixels.
+                seen += w;
+                let upto = (spare * seen).checked_div(weights).unwrap_or(0);
+                let share = upto - given;
+                given = upto;
+                share
+            }
+        }).collect()
+    }
+}
+
+impl Widget for Stack {
+    fn draw(&self, canvas: &mut Canvas, bounds: Rectangle) {
+        let total = if self.vertical { bounds.size.height } else { bounds.size.width };
+        let mut offset = 0;
+        for ((_, child), length) in self.children.iter().zip(self.lengths(total)) {
+            let area = if self.vertical {
+                Rectangle::new(bounds.top_left + Point::new(0, offset as i32), Size::new(bounds.size.width, length))
+            } else {
+                Rectangle::new(bounds.top_left + Point::new(offset as i32, 0), Size::new(length, bounds.size.height))
+            };
+            child.draw(canvas, area.intersection(&bounds));
+            offset += length + self.spacing;
+        }
+    }
+}
+
+/// Children in equal sized cells, filled a row at a time.
+pub struct Grid {
+    columns: u32,
+    rows: u32,
+    spacing: u32,
+    children: Vec<Box<dyn Widget>>,
+}
+
+impl Grid {
+    pub fn new(columns: u32, rows: u32, spacing: u32) -> Grid {
+        Grid { columns: columns.max(1), rows: rows.max(1), spacing, children: vec![] }
+    }
+
+    /// Add a child in the next free cell. Children beyond the last cell are not drawn.
+    pub fn push(mut self, child: impl Widget + 'static) -> Grid {
+        self.children.push(Box::new(child));
+        self
+    }
+}
+
+impl Widget for Grid {
+    fn draw(&self, canvas: &mut Canvas, bounds: Rectangle) {
+        let cells = cells(bounds, self.columns, self.rows, self.spacing);
+        for (child, cell) in self.children.iter().zip(cells) {
+            child.draw(canvas, cell);
+        }
+    }
+}
+
+// Equal cells of a columns x rows grid within bounds, row by row.
+fn cells(bounds: Rectangle, columns: u32, rows: u32, spacing: u32) -> Vec<Rectangle> {
+    let width = bounds.size.width.saturating_sub(spacing * (columns - 1)) / columns;
+    let height = bounds.size.height.saturating_sub(spacing * (rows - 1)) / rows;
+    (0..rows * columns).map(|i| {
+        let (column, row) = ((i % columns) as i32, (i / columns) as i32);
+        let origin = Point::new(column * (width + spacing) as i32, row * (height + spacing) as i32);
+        Rectangle::new(bounds.top_left + origin, Size::new(width, height))
+    }).collect()
+}
+
+/// Text wrapped and aligned in its area, see `layout::TextBox`.
+pub struct Label {
+    text: String,
+    fonts: Vec<&'static MonoFont<'static>>,
+    h_align: HAlign,
+    v_align: VAlign,
+}
+
+impl Label {
+    pub fn new(text: impl Into<String>, font: &'static MonoFont<'static>) -> Label {
+        Label::with_fonts(text, &[font])
+    }
+
+    /// Text in the largest of `fonts` that fits.
+    pub fn with_fonts(text: impl Into<String>, fonts: &[&'static MonoFont<'static>]) -> Label {
+        Label { text: text.into(), fonts: fonts.to_vec(), h_align: HAlign::Left, v_align: VAlign::Top }
+    }
+
+    pub fn align(mut self, h_align: HAlign, v_align: VAlign) -> Label {
+        self.h_align = h_align;
+        self.v_align = v_align;
+        self
+    }
+}
+
+impl Widget for Label {
+    fn draw(&self, canvas: &mut Canvas, bounds: Rectangle) {
+        let _ = TextBox::with_fonts(&self.text, bounds, &self.fonts)
+            .align(self.h_align, self.v_align)
+            .draw(&mut canvas.clipped(&bounds));
+    }
+}
+
+/// A short piece of text, such as a reading, scaled up by whole pixels to be
+/// as large as fits and centred.
+pub struct BigNumber {
+    text: String,
+    font: &'static MonoFont<'static>,
+}
+
+impl BigNumber {
+    pub fn new(text: impl Into<String>, font: &'static MonoFont<'static>) -> BigNumber {
+        BigNumber { text: text.into(), font }
+    }
+}
+
+impl Widget for BigNumber {
+    fn draw(&self, canvas: &mut Canvas, bounds: Rectangle) {
+        let size = Size::new(text_width(self.font, &self.text), self.font.character_size.height);
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        let mut text = Canvas::new(size);
+        let _ = Text::with_baseline(&self.text, Point::zero(), MonoTextStyle::new(self.font, BinaryColor::On), Baseline::Top)
+            .draw(&mut text);
+        let scale = (bounds.size.width / size.width).min(bounds.size.height / size.height).max(1);
+        let origin = centred(bounds, size * scale);
+        let _ = canvas.clipped(&bounds).draw_iter(text.bounding_box().points()
+            .filter(|&p| text.pixel(p) == Some(BinaryColor::On))
+            .flat_map(|p| Rectangle::new(origin + p * scale as i32, Size::new(scale, scale)).points())
+            .map(|p| Pixel(p, BinaryColor::On)));
+    }
+}
+
+/// A horizontal bar filled in proportion to a value from 0 to 1.
+pub struct ProgressBar {
+    value: f32,
+}
+
+impl ProgressBar {
+    pub fn new(value: f32) -> ProgressBar {
+        ProgressBar { value: value.clamp(0.0, 1.0) }
+    }
+}
+
+impl Widget for ProgressBar {
+    fn draw(&self, canvas: &mut Canvas, bounds: Rectangle) {
+        let _ = bounds.into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1)).draw(canvas);
+        let inner = bounds.offset(-2);
+        let filled = Size::new((inner.size.width as f32 * self.value).round() as u32, inner.size.height);
+        let _ = Rectangle::new(inner.top_left, filled).into_styled(PrimitiveStyle::with_fill(BinaryColor::On)).draw(canvas);
+    }
+}
+
+/// A line through a series of values, scaled to fill the area. With axes it
+/// becomes a simple line chart.
+pub struct Sparkline {
+    values: Vec<f32>,
+    axes: bool,
+}
+
+impl Sparkline {
+    pub fn new(values: &[f32]) -> Sparkline {
+        Sparkline { values: values.to_vec(), axes: false }
+    }
+
+    /// Draw axes along the left and bottom edges.
+    pub fn with_axes(mut self) -> Sparkline {
+        self.axes = true;
+        self
+    }
+}
+
+impl Widget for Sparkline {
+    fn draw(&self, canvas: &mut Canvas, bounds: Rectangle) {
+        let style = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+        let mut plot = bounds;
+        if self.axes {
+            if let Some(bottom_right) = bounds.bottom_right() {
+                let bottom_left = Point::new(bounds.top_left.x, bottom_right.y);
+                let _ = Line::new(bounds.top_left, bottom_left).into_styled(style).draw(canvas);
+                let _ = Line::new(bottom_left, bottom_right).into_styled(style).draw(canvas);
+            }
+            plot = Rectangle::new(bounds.top_left + Point::new(2, 0), bounds.size.saturating_sub(Size::new(2, 2)));
+        }
+        let points = plot_points(&self.values, plot);
+        let _ = Polyline::new(&points).into_styled(style).draw(canvas);
+    }
+}
+
+// Positions of values spread evenly across area, the minimum at the bottom
+// and the maximum at the top.
+fn plot_points(values: &[f32], area: Rectangle) -> Vec<Point> {
+    let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let range = if max > min { max - min } else { 1.0 };
+    let steps = (values.len() as f32 - 1.0).max(1.0);
+    let (width, height) = (area.size.width.saturating_sub(1) as f32, area.size.height.saturating_sub(1) as f32);
+    values.iter().enumerate().map(|(i, value)| {
+        let x = (i as f32 * width / steps).round() as i32;
+        let y = ((max - value) / range * height).round() as i32;
+        area.top_left + Point::new(x, y)
+    }).collect()
+}
+
+/// A battery outline filled in proportion to a charge level from 0 to 1.
+pub struct Battery {
+    level: f32,
+}
+
+impl Battery {
+    pub fn new(level: f32) -> Battery {
+        Battery { level: level.clamp(0.0, 1.0) }
+    }
+}
+
+impl Widget for Battery {
+    fn draw(&self, canvas: &mut Canvas, bounds: Rectangle) {
+        // Keep a 2:1 aspect ratio, with the terminal on the right.
+        let height = bounds.size.height.min(bounds.size.width / 2);
+        if height == 0 {
+            return;
+        }
+        let nub = (height / 4).max(1);
+        let body = Size::new((height * 2).saturating_sub(nub), height);
+        let origin = centred(bounds, Size::new(height * 2, height));
+        let outline = Rectangle::new(origin, body);
+        let _ = outline.into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1)).draw(canvas);
+        let terminal = Rectangle::new(origin + Point::new(body.width as i32, (height / 4) as i32), Size::new(nub, height / 2));
+        let _ = terminal.into_styled(PrimitiveStyle::with_fill(BinaryColor::On)).draw(canvas);
+        let inner = outline.offset(-2);
+        let filled = Size::new((inner.size.width as f32 * self.level).round() as u32, inner.size.height);
+        let _ = Rectangle::new(inner.top_left, filled).into_styled(PrimitiveStyle::with_fill(BinaryColor::On)).draw(canvas);
+    }
+}
+
+/// A QR code of some text or URL, with modules as large as fit, see `codes::Qr`.
+/// Nothing is drawn if the data is too long to encode.
+pub struct QrCode {
+    data: String,
+    ecc: EccLevel,
+}
+
+impl QrCode {
+    pub fn new(data: impl Into<String>) -> QrCode {
+        QrCode { data: data.into(), ecc: EccLevel::Medium }
+    }
+
+    /// Set the error correction level.
+    pub fn ecc(mut self, ecc: EccLevel) -> QrCode {
+        self.ecc = ecc;
+        self
+    }
+}
+
+impl Widget for QrCode {
+    fn draw(&self, canvas: &mut Canvas, bounds: Rectangle) {
+        if let Ok(qr) = Qr::new(self.data.as_bytes(), self.ecc) {
+            let _ = qr.fit(bounds).draw(canvas);
+        }
+    }
+}
+
+/// Icons arranged in a grid, each centred in its cell.
+pub struct IconGrid {
+    columns: u32,
+    icons: Vec<ImageRaw<'static, BinaryColor>>,
+}
+
+impl IconGrid {
+    pub fn new(columns: u32, icons: Vec<ImageRaw<'static, BinaryColor>>) -> IconGrid {
+        IconGrid { columns: columns.max(1), icons }
+    }
+}
+
+impl Widget for IconGrid {
+    fn draw(&self, canvas: &mut Canvas, bounds: Rectangle) {
+        let rows = (self.icons.len() as u32).div_ceil(self.columns).max(1);
+        for (icon, cell) in self.icons.iter().zip(cells(bounds, self.columns, rows, 0)) {
+            let _ = Image::new(icon, centred(cell, icon.size())).draw(&mut canvas.clipped(&cell));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::mock_display::MockDisplay;
+    use embedded_graphics::mono_font::ascii::FONT_6X10;
+
+    fn render(widget: &dyn Widget, size: Size) -> Canvas {
+        let mut canvas = Canvas::new(size);
+        widget.draw(&mut canvas, Rectangle::new(Point::zero(), size));
+        canvas
+    }
+
+    fn lit(canvas: &Canvas) -> usize {
+        canvas.pixels.iter().filter(|&&on| on).count()
+    }
+
+    #[test]
+    fn stack_lengths() {
+        let stack = Stack::row(2)
+            .push(Length::Fixed(10), Label::new("a", &FONT_6X10))
+            .push(Length::Fill(1), Label::new("b", &FONT_6X10))
+            .push(Length::Fill(2), Label::new("c", &FONT_6X10));
+        assert_eq!(stack.lengths(50), vec![10, 12, 24]);
+        assert_eq!(stack.lengths(5), vec![10, 0, 0]);
+    }
+
+    #[test]
+    fn grid_cells() {
+        let bounds = Rectangle::new(Point::new(1, 1), Size::new(21, 10));
+        let cells = cells(bounds, 2, 2, 1);
+        assert_eq!(cells[0], Rectangle::new(Point::new(1, 1), Size::new(10, 4)));
+        assert_eq!(cells[3], Rectangle::new(Point::new(12, 6), Size::new(10, 4)));
+    }
+
+    #[test]
+    fn progress_and_battery() {
+        let size = Size::new(24, 8);
+        let empty = lit(&render(&ProgressBar::new(0.0), size));
+        let half = lit(&render(&ProgressBar::new(0.5), size));
+        let full = lit(&render(&ProgressBar::new(1.5), size));
+        assert!(empty < half && half < full);
+        assert_eq!(full - empty, 20 * 4);
+        assert!(lit(&render(&Battery::new(0.2), size)) < lit(&render(&Battery::new(0.9), size)));
+    }
+
+    #[test]
+    fn tiny_bounds() {
+        const ICON: [u8; 2] = [0b1000_0000, 0b0100_0000];
+        let widgets: Vec<Box<dyn Widget>> = vec![
+            Box::new(Stack::column(2).push(Length::Fixed(4), ProgressBar::new(0.5)).push(Length::Fill(1), Battery::new(0.5))),
+            Box::new(Grid::new(2, 2, 1).push(Battery::new(1.0)).push(Sparkline::new(&[1.0, 2.0]).with_axes())),
+            Box::new(Label::new("Hello", &FONT_6X10)),
+            Box::new(BigNumber::new("42", &FONT_6X10)),
+            Box::new(ProgressBar::new(0.5)),
+            Box::new(Sparkline::new(&[1.0, 3.0, 2.0]).with_axes()),
+            Box::new(Battery::new(0.5)),
+            Box::new(QrCode::new("https://example.com")),
+            Box::new(IconGrid::new(2, vec![ImageRaw::new(&ICON, 2)])),
+        ];
+        for widget in &widgets {
+            for size in [Size::zero(), Size::new(1, 1), Size::new(1, 10), Size::new(10, 1)] {
+                render(widget.as_ref(), size);
+            }
+        }
+    }
+
+    #[test]
+    fn sparkline_scaling() {
+        let area = Rectangle::new(Point::new(0, 0), Size::new(11, 5));
+        assert_eq!(plot_points(&[1.0, 3.0, 2.0], area), vec![Point::new(0, 4), Point::new(5, 0), Point::new(10, 2)]);
+        // A flat line doesn't divide by zero.
+        assert_eq!(plot_points(&[2.0, 2.0], area), vec![Point::new(0, 0), Point::new(10, 0)]);
+    }
+
+    #[test]
+    fn big_number_scales_up() {
+        let small = lit(&render(&BigNumber::new("42", &FONT_6X10), Size::new(12, 10)));
+        let big = lit(&render(&BigNumber::new("42", &FONT_6X10), Size::new(36, 30)));
+        assert_eq!(big, small * 9);
+    }
+
+    #[test]
+    fn qr_code_fits() {
+        let canvas = render(&QrCode::new("https://example.com"), Size::new(66, 66));
+        let area = MockDisplay::<BinaryColor>::from_points(
+            canvas.bounding_box().points().filter(|&p| canvas.pixel(p) == Some(BinaryColor::On)), BinaryColor::On
+        ).affected_area();
+        // Version 2 (25 modules, 33 with the quiet zone) at 2 pixels per module.
+        assert_eq!(area.size, Size::new(50, 50));
+        assert_eq!(area.top_left, Point::new(8, 8));
+    }
+
+    #[test]
+    fn screen_dirty_rectangles() {
+        let mut display = MockDisplay::<BinaryColor>::new();
+        display.set_allow_overdraw(true);
+        let mut screen = Screen::new();
+        let dirty = screen.render(&ProgressBar::new(0.25), &mut display).expect("render");
+        assert_eq!(dirty, vec![display.bounding_box()]);
+        assert!(screen.render(&ProgressBar::new(0.25), &mut display).expect("render").is_empty());
+        let dirty = screen.render(&ProgressBar::new(0.5), &mut display).expect("render");
+        assert_eq!(dirty, vec![Rectangle::new(Point::new(17, 2), Size::new(15, 60))]);
+    }
+}